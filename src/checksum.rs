@@ -0,0 +1,241 @@
+/// The algorithm computing the checksums of the generated code.
+///
+/// Except for [`Checksum_Algorithm::BLAKE3`], the end marker is tagged with the
+/// algorithm, like `<< /codegen sha256:ab12 >>`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Checksum_Algorithm
+{
+  #[default]
+  BLAKE3,
+  SHA256,
+  /// Short checksums, only suitable to detect accidental edits
+  CRC32,
+  /// blake3 in keyed mode. Only someone knowing the [`Checksum_Key`] can
+  /// compute a valid checksum, so hand edits can't be disguised. At least
+  /// 16 bytes are stored.
+  BLAKE3_MAC,
+}
+
+impl Checksum_Algorithm
+{
+  pub const ALL : [Self; 4] = [Self::BLAKE3, Self::SHA256, Self::CRC32, Self::BLAKE3_MAC];
+
+  /// The tag used in the end marker
+  pub fn tag(self) -> &'static str
+  {
+    match self
+    {
+      Self::BLAKE3 => "blake3",
+      Self::SHA256 => "sha256",
+      Self::CRC32 => "crc32",
+      Self::BLAKE3_MAC => "mac",
+    }
+  }
+
+  pub fn from_tag(tag: &str) -> Option<Self>
+  {
+    Self::ALL.into_iter().find(|algorithm| algorithm.tag() == tag)
+  }
+
+  /// Number of bytes of a full checksum
+  pub fn output_len(self) -> usize
+  {
+    match self
+    {
+      Self::BLAKE3 => blake3::OUT_LEN,
+      Self::SHA256 => 32,
+      Self::CRC32 => 4,
+      Self::BLAKE3_MAC => blake3::OUT_LEN,
+    }
+  }
+
+  /// The number of bytes stored in the end marker, when configured to store
+  /// `bytes_to_store` bytes
+  pub fn stored_len(self, bytes_to_store: u8) -> usize
+  {
+    let len = (bytes_to_store as usize).min(self.output_len());
+    match self.is_keyed()
+    {
+      true => len.max(MIN_MAC_LEN),
+      false => len,
+    }
+  }
+
+  /// Whether computing a checksum requires a [`Checksum_Key`]
+  pub fn is_keyed(self) -> bool
+  {
+    self == Self::BLAKE3_MAC
+  }
+
+  /// # Panics
+  ///
+  /// Panics for keyed algorithms. Use [`Checksum_Algorithm::checksum_with_key`]
+  /// for them.
+  pub fn checksum(self, code: &[u8]) -> Checksum
+  {
+    self.checksum_with_key(code, None).expect("the checksum algorithm requires a key")
+  }
+
+  /// Returns `None` if the algorithm is keyed, but no `key` was given
+  pub fn checksum_with_key(self, code: &[u8], key: Option<&Checksum_Key>) -> Option<Checksum>
+  {
+    let mut bytes = [0; MAX_LEN];
+    match self
+    {
+      Self::BLAKE3 => bytes.copy_from_slice(blake3::hash(code).as_bytes()),
+      Self::BLAKE3_MAC => bytes.copy_from_slice(blake3::keyed_hash(&key?.0, code).as_bytes()),
+      Self::SHA256 => bytes.copy_from_slice(&sha2::Sha256::digest(code)),
+      Self::CRC32 => bytes[..4].copy_from_slice(&crc32fast::hash(code).to_be_bytes()),
+    }
+    Some(Checksum{algorithm: self, bytes})
+  }
+}
+
+/// The secret key for [`Checksum_Algorithm::BLAKE3_MAC`]
+///
+/// Not `Copy`, so the secret isn't spread over the stack by accident. Pass it
+/// by reference, e.g. as [`crate::Config::checksum_key`].
+#[derive(Clone)]
+pub struct Checksum_Key([u8; blake3::KEY_LEN]);
+
+impl Checksum_Key
+{
+  const CONTEXT : &'static str = "codebiber 2023-12-01 section checksum key";
+
+  pub fn new(key: [u8; blake3::KEY_LEN]) -> Self
+  {
+    Checksum_Key(key)
+  }
+
+  /// Derives the key from a secret of any length
+  pub fn derive(secret: &[u8]) -> Self
+  {
+    Checksum_Key(blake3::derive_key(Self::CONTEXT, secret))
+  }
+
+  /// Derives the key from the content of a key file
+  pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self>
+  {
+    Ok(Self::derive(&std::fs::read(path)?))
+  }
+}
+
+/// Compares in constant time, so the key can't be guessed byte by byte
+impl PartialEq for Checksum_Key
+{
+  fn eq(&self, other: &Self) -> bool
+  {
+    self.0.iter().zip(other.0.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+  }
+}
+
+impl Eq for Checksum_Key {}
+
+impl fmt::Debug for Checksum_Key
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    f.write_str("Checksum_Key(..)")
+  }
+}
+
+/// The longest checksum of all algorithms
+pub const MAX_LEN : usize = 32;
+
+/// The shortest MAC accepted, so it can't be guessed
+pub const MIN_MAC_LEN : usize = 16;
+
+/// A full checksum
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Checksum
+{
+  algorithm: Checksum_Algorithm,
+  bytes: [u8; MAX_LEN],
+}
+
+impl Checksum
+{
+  pub fn algorithm(&self) -> Checksum_Algorithm
+  {
+    self.algorithm
+  }
+
+  pub fn as_bytes(&self) -> &[u8]
+  {
+    &self.bytes[..self.algorithm.output_len()]
+  }
+
+  /// Whether `stored` is the beginning of this checksum.
+  ///
+  /// Compares in constant time, so a MAC can't be guessed byte by byte.
+  pub fn matches(&self, stored: &[u8]) -> bool
+  {
+    let actual = self.as_bytes();
+    stored.len() <= actual.len() && stored.iter().zip(actual).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+  }
+}
+
+impl fmt::Display for Checksum
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    self.as_bytes().iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+  }
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  #[test]
+  fn test_checksum()
+  {
+    assert_eq!(Checksum_Algorithm::BLAKE3.checksum(b"42").as_bytes(), blake3::hash(b"42").as_bytes());
+    assert_eq!(Checksum_Algorithm::BLAKE3.checksum(b"42").to_string(), blake3::hash(b"42").to_hex().as_str());
+    assert_eq!(Checksum_Algorithm::SHA256.checksum(b"abc").to_string(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(Checksum_Algorithm::CRC32.checksum(b"123456789").to_string(), "cbf43926");
+
+    let key = Checksum_Key::new([42; 32]);
+    assert_eq!(Checksum_Algorithm::BLAKE3_MAC.checksum_with_key(b"42", Some(&key)).unwrap().as_bytes(), blake3::keyed_hash(&[42; 32], b"42").as_bytes());
+    assert_eq!(Checksum_Algorithm::BLAKE3_MAC.checksum_with_key(b"42", None), None);
+    assert_ne!(Checksum_Key::derive(b"secret"), Checksum_Key::derive(b"other secret"));
+    assert_eq!(format!("{key:?}"), "Checksum_Key(..)");
+  }
+
+  #[test]
+  fn test_tags()
+  {
+    for algorithm in Checksum_Algorithm::ALL
+    {
+      assert_eq!(Checksum_Algorithm::from_tag(algorithm.tag()), Some(algorithm));
+      assert!(algorithm.output_len() <= MAX_LEN);
+    }
+    assert_eq!(Checksum_Algorithm::from_tag("md5"), None);
+  }
+
+  #[test]
+  fn test_matches()
+  {
+    let checksum = Checksum_Algorithm::CRC32.checksum(b"123456789");
+    assert!(checksum.matches(&[]));
+    assert!(checksum.matches(&[0xcb, 0xf4]));
+    assert!(checksum.matches(&[0xcb, 0xf4, 0x39, 0x26]));
+    assert!(!checksum.matches(&[0xcb, 0xf5]));
+    assert!(!checksum.matches(&[0xcb, 0xf4, 0x39, 0x26, 0]));
+  }
+
+  #[test]
+  fn test_stored_len()
+  {
+    assert_eq!(Checksum_Algorithm::BLAKE3.stored_len(0), 0);
+    assert_eq!(Checksum_Algorithm::CRC32.stored_len(8), 4);
+    assert_eq!(Checksum_Algorithm::BLAKE3.stored_len(40), 32);
+    assert_eq!(Checksum_Algorithm::BLAKE3_MAC.stored_len(0), MIN_MAC_LEN);
+    assert_eq!(Checksum_Algorithm::BLAKE3_MAC.stored_len(20), 20);
+  }
+}
+
+use sha2::Digest;
+use std::fmt;
+use std::path::Path;
@@ -1,103 +1,1136 @@
 use super::*;
 
-use super::parse_file::{find as parse_sections, Section};
-use indentation::ensure_tailing_linebreak;
+use super::checksum::{Checksum, Checksum_Algorithm, Checksum_Key, MIN_MAC_LEN};
+use super::unified_diff::{unified_diff, Changed_Section};
+use super::parse_file::{find_sections, find_marker_line, end_marker_name, escape_markers, find_user_regions, strip_user_regions, restore_user_regions, User_Region_Error, Section, Section_Parts, Marker, Inline_Line, Arguments};
+use indentation::{ensure_tailing_linebreak, Indent_Style};
+use line_ending::normalize as normalize_line_endings;
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
-pub struct Config
+mod generator;
+pub use generator::{Generator, Generator_Return, Generator_Output};
+
+mod section_context;
+pub use section_context::Section_Context;
+
+mod stale_section;
+pub use stale_section::{Stale_Section, Stale_Reason};
+
+mod report;
+pub use report::{Generate_Report, Section_Report, Section_Status};
+
+mod lint;
+pub use lint::{lint, format_markers, Lint, Lint_Kind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config<'a>
 {
+  /// Bytes beyond the output length of the checksum algorithm are ignored
   pub checksum_bytes_to_store: u8,
+  /// If non-zero, the leading whitespace of generated code is converted to
+  /// the style (tabs or spaces) of the marker's indentation, assuming tabs of
+  /// this width. Markers without indentation take the style most indented
+  /// lines of the file use. 0 keeps the generated code as it is.
+  ///
+  /// If non-zero, the code of a section may also be indented by a different
+  /// mix of tabs and spaces than its markers, as long as it has the same width.
+  pub tab_width: u8,
+  pub marker_style: Marker_Style<'a>,
+  /// Generated code must not contain marker lines, as they would corrupt the
+  /// file. By default, this is reported as [`Gen_Error::FORBIDDEN`]. If set,
+  /// marker lines are escaped instead (see [`crate::parse_file::escape_markers`]
+  /// and [`crate::parse_file::unescape_markers`]).
+  pub escape_markers: bool,
+  /// What to do with sections which were edited by hand since they were
+  /// generated
+  pub on_hand_edit: Hand_Edit_Policy,
+  /// Used for writing checksums. Checksums are verified with the algorithm
+  /// they are tagged with, so changing this migrates the files when they are
+  /// generated the next time.
+  pub checksum_algorithm: Checksum_Algorithm,
+  /// The key for [`Checksum_Algorithm::BLAKE3_MAC`], either given directly or
+  /// loaded with [`Checksum_Key::from_file`]
+  pub checksum_key: Option<&'a Checksum_Key>,
+  /// The checksum also covers the identifier and the arguments of the
+  /// section, so renaming a section or moving code between sections is
+  /// detected as a hand edit. Use [`migrate_checksums`] after switching it.
+  pub checksum_covers_identifier: bool,
+  /// Copies the marker lines byte-for-byte from the input, replacing only the
+  /// checksum. Otherwise, the markers are rewritten with the
+  /// [`Config::marker_style`] and the end marker is indented like its begin
+  /// marker.
+  pub preserve_marker_lines: bool,
+  /// Fails with [`Gen_Error::UNCLAIMED_SECTIONS`] if the generator returns
+  /// [`Generator_Output::NOT_MINE`] or `None` for
+  /// any section, to catch typos in the identifiers. Otherwise, these sections
+  /// are kept as they are.
+  pub strict: bool,
+  /// Keeps the handwritten content of the user regions inside of sections,
+  /// marked with `<< keep name >>` and `<< /keep >>`, when the sections are
+  /// regenerated, and leaves it out of the checksum. Otherwise, these markers
+  /// are ordinary code.
+  pub user_regions: bool,
+  /// Whether sections may contain sections. The checksum of a section covers
+  /// its whole code, including the nested sections, and is computed after
+  /// these were generated.
+  pub nesting: Nesting,
+  /// With [`Config::nesting`], the sections in the code returned by a
+  /// generator are generated, too. Nested sections the outer generator leaves
+  /// empty keep their previous code. Otherwise, nested sections are only
+  /// generated if the outer generator keeps its code, and marker lines in
+  /// generated code are handled like without nesting, see
+  /// [`Config::escape_markers`].
+  pub expand_generated_sections: bool,
+  /// The maximum number of sections a section may be nested in with
+  /// [`Config::expand_generated_sections`]. Protects against generators
+  /// expanding endlessly, see [`Gen_Error::NESTING_TOO_DEEP`].
+  pub max_nesting_depth: u8,
+  /// Writes the identifier into the end markers, e.g. `<< /codegen foo >>`, so
+  /// an end marker closing the wrong section is detected. Named end markers are
+  /// verified and kept regardless of this setting. Marker styles without a
+  /// closing delimiter, like [`Marker_Style::REGION`], don't support names.
+  ///
+  /// A single untagged token like `<< /codegen cafe >>` is then read as a
+  /// name, so a checksum must follow the name or be tagged. Without it, the
+  /// token is a name, if it's the identifier of the section or can't be a
+  /// checksum, and a checksum otherwise. [`migrate_checksums`] and
+  /// [`format_markers`] read it the latter way, so they add the names to
+  /// end markers written without this setting.
+  pub named_end_markers: bool,
+}
+
+/// How to resolve sections whose code doesn't match their checksum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hand_Edit_Policy
+{
+  /// Fail with [`Gen_Error::WRONG_CHECKSUM`]
+  #[default]
+  ERROR,
+  /// Overwrite the hand edit with the generated code. The section is reported
+  /// as [`Stale_Reason::CHECKSUM_MISMATCH`] by [`check`] and [`diff`] and as
+  /// [`Section_Status::OVERWRITTEN`] by [`generate_with_report`] and
+  /// [`crate::process_files_with_report`], see [`Generate_Report::overwritten`].
+  OVERWRITE,
+  /// Keep the hand edit as it is, without calling the generator
+  KEEP,
+  /// Write the hand edit and the generated code separated by conflict
+  /// markers. The checksum of the generated code is stored, so the section is
+  /// reported as hand-edited until the conflict is resolved. Regenerating an
+  /// unresolved conflict only replaces its generated code. Sections containing
+  /// nested sections or user regions fail with
+  /// [`Gen_Error::CONFLICT_WITH_MARKERS`] instead, as these would be written
+  /// twice.
+  CONFLICT,
+}
+
+/// Whether sections may contain sections and in which order they are generated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Nesting
+{
+  /// Begin markers inside of sections are reported as
+  /// [`Parse_Error::NESTED_BEGIN_MARKER`](crate::parse_file::Parse_Error::NESTED_BEGIN_MARKER)
+  #[default]
+  FORBIDDEN,
+  /// The outer generator is called first. The sections in its code are
+  /// generated afterwards.
+  OUTER_FIRST,
+  /// The nested sections are generated first, so the outer generator sees
+  /// their new code in [`Section_Context::old_code`].
+  DEPTH_FIRST,
 }
 
 pub type Fmt_Result<T=Option<String>> = std::result::Result<T, std::fmt::Error>;
 
-pub fn generate<F>(input: &str, cfg: Config, mut f: F) -> Result<Option<String>>
-where F: FnMut(&str) -> Fmt_Result
+/// The result of a generator failing with the error type `E`
+pub type Gen_Result<E, T=Option<String>> = std::result::Result<T, E>;
+
+/// Generates the code of each section with `f`.
+///
+/// `f` gets the identifier of the section, its identifier and arguments, or a
+/// [`Section_Context`] with everything known about it, see [`Generator`].
+/// Errors returned by `f` are passed on as [`Gen_Error::GENERATOR`].
+pub fn generate<G, I>(input: &str, cfg: Config, f: G) -> Result<Option<String>, Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  generate_file(input, cfg, None, f)
+}
+
+/// Like [`generate`], but also returns what happened to each section.
+pub fn generate_with_report<G, I>(input: &str, cfg: Config, f: G) -> Result<(Option<String>, Generate_Report), Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  generate_file_with_report(input, cfg, None, f)
+}
+
+/// Rewrites the checksums of all sections with [`Config::checksum_algorithm`]
+/// and [`Config::checksum_bytes_to_store`], keeping their code.
+///
+/// The existing checksums are verified with the algorithm they are tagged with.
+pub fn migrate_checksums(input: &str, cfg: Config) -> Result<Option<String>>
+{
+  let generated = generate_and_check(input, cfg, None, Mode::MIGRATE, |_: &Section_Context| Generator_Output::KEEP)?;
+  Ok(if generated.stale.is_empty() {None} else {Some(generated.code)})
+}
+
+/// Runs the generators like [`generate`], but returns the sections which would
+/// change instead of the generated code.
+///
+/// Hand-edited sections are reported as [`Stale_Reason::CHECKSUM_MISMATCH`]
+/// instead of failing. Their generator is not called. With
+/// [`Hand_Edit_Policy::KEEP`], they aren't stale, as [`generate`] keeps them.
+pub fn check<G, I>(input: &str, cfg: Config, f: G) -> Result<Vec<Stale_Section>, Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  check_file(input, cfg, None, f)
+}
+
+/// Returns a unified diff of the sections which would change by [`generate`].
+///
+/// There is one hunk per changed section, annotated with its identifier.
+/// Returns `None` if nothing would change.
+pub fn diff<G, I>(input: &str, cfg: Config, f: G) -> Result<Option<String>, Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  Ok(diff_file(input, cfg, None, "input", f)?.map(|(_, diff)| diff))
+}
+
+pub(crate) fn generate_file<G, I>(input: &str, cfg: Config, path: Option<&Path>, f: G) -> Result<Option<String>, Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  let generated = generate_and_check(input, cfg, path, Mode::GENERATE, f)?;
+  return if generated.stale.is_empty() {Ok(None)} else {Ok(Some(generated.code))};
+}
+
+pub(crate) fn generate_file_with_report<G, I>(input: &str, cfg: Config, path: Option<&Path>, f: G) -> Result<(Option<String>, Generate_Report), Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  let generated = generate_and_check(input, cfg, path, Mode::GENERATE, f)?;
+  let code = if generated.stale.is_empty() {None} else {Some(generated.code)};
+  Ok((code, generated.report))
+}
+
+pub(crate) fn check_file<G, I>(input: &str, cfg: Config, path: Option<&Path>, f: G) -> Result<Vec<Stale_Section>, Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  Ok(generate_and_check(input, cfg, path, Mode::CHECK, f)?.stale)
+}
+
+/// Returns the unified diff of regenerating `input`, if any section changed.
+///
+/// `label` is used as file name in the header of the diff.
+pub(crate) fn diff_file<G, I>(input: &str, cfg: Config, path: Option<&Path>, label: &str, f: G) -> Result<Option<(String, String)>, Gen_Error<G::Error>>
+where G: Generator<I>
+{
+  let generated = generate_and_check(input, cfg, path, Mode::GENERATE, f)?;
+  let sections = generated.stale.iter().zip(generated.new_lines.iter()).map(|(stale, new_lines)| Changed_Section{
+    identifier: &stale.identifier,
+    hand_edited: stale.reason.is_hand_edit(),
+    old_lines: stale.lines.clone(),
+    new_lines: new_lines.clone(),
+  });
+  let diff = unified_diff(input, &generated.code, label, label, sections);
+  Ok(diff.map(|diff| (generated.code, diff)))
+}
+
+#[derive(Default)]
+struct Generated
+{
+  code: String,
+  stale: Vec<Stale_Section>,
+  /// 1-based line numbers of the begin and end marker of each stale section
+  /// in `code`
+  new_lines: Vec<RangeInclusive<usize>>,
+  report: Generate_Report,
+  unclaimed: Vec<Unclaimed_Section>,
+}
+
+type Dyn_Generator<'f, E> = dyn FnMut(&Section_Context) -> Result<Generator_Output, E> + 'f;
+
+/// Where the generated text is located
+#[derive(Clone, Copy)]
+struct Scope<'a>
+{
+  path: Option<&'a Path>,
+  /// 1-based line number of the first line of the text in the file
+  first_line: usize,
+  /// The identifiers and arguments of the sections the text is nested in,
+  /// the innermost last
+  ancestors: &'a [(&'a str, &'a str)],
+  /// The identifiers and arguments of the sections whose generators returned
+  /// the text, the innermost last. Sections nested in the input don't count.
+  expanded_from: &'a [(&'a str, &'a str)],
+  /// The indentation style of the whole file
+  indent_style: Option<Indent_Style>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode
+{
+  GENERATE,
+  /// Reports hand edits instead of failing
+  CHECK,
+  /// Accepts checksums which would be rejected when generating, as
+  /// [`migrate_checksums`] rewrites them on purpose
+  MIGRATE,
+}
+
+/// Generates the code and collects the sections which changed.
+///
+/// With [`Mode::CHECK`], hand-edited sections are reported as stale and left
+/// out of the generated code instead of failing, unless they are kept by
+/// [`Hand_Edit_Policy::KEEP`].
+fn generate_and_check<G, I>(input: &str, cfg: Config, path: Option<&Path>, mode: Mode, mut f: G) -> Result<Generated, Gen_Error<G::Error>>
+where G: Generator<I>
 {
   debug_assert!(cfg.is_valid());
 
-  use Section::*;
-  let sections = parse_sections(input)?;
+  let generated = generate_sections(input, cfg, Scope{path, first_line: 1, ancestors: &[], expanded_from: &[], indent_style: Indent_Style::detect(input)}, mode, &mut |ctx: &Section_Context| f.generate(ctx))?;
+  if !generated.unclaimed.is_empty()
+  {
+    return Err(Gen_Error::UNCLAIMED_SECTIONS(generated.unclaimed));
+  }
 
-  match &sections[..]
+  Ok(generated)
+}
+
+fn generate_sections<E>(input: &str, cfg: Config, scope: Scope, mode: Mode, f: &mut Dyn_Generator<E>) -> Result<Generated, Gen_Error<E>>
+{
+  let Scope{path, first_line, ..} = scope;
+  let sections = find_sections(input, &cfg.marker_style, cfg.nesting != Nesting::FORBIDDEN, named_end_markers(cfg, mode)).map_err(|e| e.with_path(path).with_line_offset(first_line-1))?;
+
+  let mut generated = Generated::default();
+  if matches!(&sections[..], &[] | &[Section::HANDWRITTEN(_)])
   {
-    &[] | &[HANDWRITTEN(_)] => return Ok(None),
-    _ => (),
+    return Ok(generated);
   }
 
-  let mut generated = String::with_capacity(input.len()+4096);
-  let mut changed = false;
+  let nl = Line_Ending::detect(input);
+  generated.code.reserve(input.len()+4096);
+  let mut line = first_line;
+  let mut new_line = first_line;
+  let mut index = 0;
+
+  // A file ending with an end marker keeps ending without a line break
+  let last_section = sections.len() - 1;
+  let final_linebreak = input.ends_with('\n');
 
-  for sec in sections.iter()
+  for (section_index_in_file, sec) in sections.iter().enumerate()
   {
-    match sec
+    let begin_line = line;
+    line += sec.line_count();
+    let parts = match sec.parts()
     {
-      HANDWRITTEN(code) => generated += code,
-      CODEGEN { identifier, code: old_code, checksum: old_checksum, begin, end } =>
+      Some(parts) => parts,
+      None =>
       {
-        let old_code = begin.indentation.unindent_str(old_code)?;
-        check_code_checksum(&old_code, old_checksum)?;
-        let old_checksum =
+        generated.code += sec.code();
+        new_line += sec.line_count();
+        continue;
+      }
+    };
+    let section = Section_Job{parts, lines: begin_line..=line-1, location: Location::new(path, begin_line, parts.begin.column()), path, index};
+    index += 1;
+
+    if cfg.expand_generated_sections
+    {
+      check_expansion(&section, scope, cfg)?;
+    }
+    let old_code = unindented_code(&section, cfg)?;
+    let checked = check_section(&section, &old_code, cfg, mode)?;
+    let hand_edited = match checked.hand_edit
+    {
+      None => false,
+      // A kept hand edit would stay as it is, so it isn't stale
+      Some(hand_edit) if mode == Mode::CHECK && cfg.on_hand_edit != Hand_Edit_Policy::KEEP =>
+      {
+        generated.stale.push(Stale_Section{identifier: hand_edit.identifier.to_string(), lines: hand_edit.lines.clone(), reason: checked.hand_edit_reason});
+        generated.new_lines.push(hand_edit.lines);
+        continue;
+      }
+      Some(hand_edit) =>
+      {
+        fail_on_hand_edit(hand_edit, checked.hand_edit_reason, cfg, parts.inline)?;
+        true
+      }
+    };
+
+    let keep_hand_edit = hand_edited && cfg.on_hand_edit == Hand_Edit_Policy::KEEP;
+    let nested = cfg.nesting != Nesting::FORBIDDEN && !keep_hand_edit && !parts.inline;
+    let nested_ancestors = [scope.ancestors, &[(parts.identifier, parts.arguments.source)]].concat();
+    let nested_scope = |first_line| Scope{first_line, ancestors: &nested_ancestors, ..scope};
+    let expanded_from = [scope.expanded_from, &[(parts.identifier, parts.arguments.source)]].concat();
+
+    // Depth first, the outer generator sees the regenerated nested sections
+    let mut regenerated = Vec::new();
+    let mut depth_first = match nested && cfg.nesting == Nesting::DEPTH_FIRST && !parts.children.is_empty()
+    {
+      true => Some(generate_depth_first(&old_code, cfg, nested_scope(begin_line+1), mode, f, &mut regenerated)?),
+      false => None,
+    };
+    if let Some(depth_first) = &mut depth_first
+    {
+      generated.unclaimed.append(&mut depth_first.unclaimed);
+    }
+    let base_code = depth_first.as_ref().map_or(old_code.as_str(), |depth_first| depth_first.code.as_str());
+
+    let generated_code = match keep_hand_edit
+    {
+      true => None,
+      false => run_generator(&section, base_code, scope, cfg, f, &mut generated.unclaimed)?,
+    };
+    let kept = generated_code.is_none();
+    let expand = nested && cfg.expand_generated_sections;
+    let new_code = match generated_code
+    {
+      Some(new_code) => Some(prepare_generated_code(new_code, &section, &old_code, expand, cfg, scope.indent_style)?),
+      None => None,
+    };
+    // Without expansion, the nested sections are only generated where the
+    // outer section keeps its code
+    let (new_code, mut nested_reports) = match nested && (expand || new_code.is_none())
+    {
+      false => (new_code, Vec::new()),
+      true =>
+      {
+        // Only the sections in the generated code were expanded from this section
+        let children_scope = match new_code.is_some()
+        {
+          true => Scope{expanded_from: &expanded_from, ..nested_scope(new_line+1)},
+          false => nested_scope(new_line+1),
+        };
+        // Nested sections left empty by the outer generator keep their code
+        let skeleton = carry_nested_code(new_code.as_deref().unwrap_or(base_code), base_code, begin_line+1, children_scope, cfg, mode)?;
+        let children = generate_nested_skeleton(&skeleton, &regenerated, cfg, children_scope, mode, f)?;
+        generated.unclaimed.extend(children.unclaimed);
+        let nested_reports = merge_nested_reports(children.report.sections, depth_first.map_or_else(Vec::new, |depth_first| depth_first.report.sections), begin_line, new_line);
+        match new_code.is_none() && children.code == old_code
+        {
+          true => (None, nested_reports),
+          false => (Some(children.code), nested_reports),
+        }
+      }
+    };
+
+    let end_name = end_name(cfg, parts.identifier, parts.end);
+    let stored_checksum = format_checksum(parts.checksum, parts.checksum.len(), parts.checksum_algorithm);
+    let (new_code, checksum_text, reason) = match new_code
+    {
+      // The hand edit is kept as it is, including its checksum
+      None if hand_edited => (old_code, stored_checksum.clone(), None),
+      None =>
+      {
+        let checksum_text = checksum_text(&old_code, parts, cfg);
+        (old_code, checksum_text, checked.unchanged_reason)
+      }
+      Some(new_code) =>
+      {
+        let reason = match (hand_edited, new_code != old_code)
         {
-          let actual_checksum = blake3::hash(old_code.as_bytes());
-          changed = changed || cfg.checksum_bytes_to_store as usize != old_checksum.len();
-          actual_checksum
+          (true, _) => Some(checked.hand_edit_reason),
+          (false, true) => Some(Stale_Reason::CONTENT_CHANGED),
+          (false, false) => checked.unchanged_reason,
         };
+        let checksum_text = checksum_text(&new_code, parts, cfg);
+        // The checksum is the one of the generated code, so the conflict is reported until it's resolved
+        let new_code = match hand_edited && cfg.on_hand_edit == Hand_Edit_Policy::CONFLICT
+        {
+          true => conflict_code(&old_code, new_code, &section, cfg)?,
+          false => new_code,
+        };
+        (new_code, checksum_text, reason)
+      }
+    };
+    write_section(&mut generated.code, parts, &new_code, end_name, &checksum_text, cfg, nl);
+    if final_linebreak || section_index_in_file != last_section
+    {
+      generated.code += nl.as_str();
+    }
 
-        write!(&mut generated, "{i}{before}<< codegen {ident} >>{after}\n", i=begin.indentation, before=begin.before_marker, after=begin.after_marker, ident=identifier)?;
-        
-        let new_code = f(identifier)?.map(ensure_tailing_linebreak).unwrap_or(old_code);
-        let new_checksum = blake3::hash(new_code.as_bytes());
-        generated += begin.indentation.indent_str(new_code.as_str()).as_str();
+    let new_begin_line = new_line;
+    new_line += match parts.inline
+    {
+      true => 1,
+      false => 2 + count_lines(&new_code),
+    };
+    generated.report.sections.push(Section_Report{
+      identifier: parts.identifier.to_string(),
+      old_lines: section.lines.clone(),
+      new_lines: new_begin_line..=new_line-1,
+      status: section_status(reason, kept, cfg),
+      old_checksum: stored_checksum,
+      new_checksum: checksum_text,
+    });
+    generated.report.sections.append(&mut nested_reports);
+    if let Some(reason) = reason
+    {
+      generated.stale.push(Stale_Section{identifier: parts.identifier.to_string(), lines: section.lines, reason});
+      generated.new_lines.push(new_begin_line..=new_line-1);
+    }
+  }
+
+  Ok(generated)
+}
 
-        write!(&mut generated, "{i}{before}<< /codegen ", i=begin.indentation, before=end.before_marker)?;
-        if cfg.checksum_bytes_to_store > 0
+/// A section of the text passed to [`generate_sections`]
+struct Section_Job<'s, 'a>
+{
+  parts: Section_Parts<'s, 'a>,
+  /// 1-based line numbers of the begin and end marker
+  lines: RangeInclusive<usize>,
+  /// The location of the begin marker
+  location: Location,
+  path: Option<&'s Path>,
+  /// See [`Section_Context::index`]
+  index: usize,
+}
+
+impl Section_Job<'_, '_>
+{
+  fn begin_line(&self) -> usize
+  {
+    *self.lines.start()
+  }
+
+  fn user_region_error<E>(&self, error: User_Region_Error) -> Gen_Error<E>
+  {
+    Gen_Error::USER_REGION{
+      location: Location::new(self.path, self.begin_line() + error.line(), self.parts.begin.column()),
+      identifier: self.parts.identifier.to_string(),
+      error: Box::new(error),
+    }
+  }
+}
+
+/// Fails for sections generating themselves again or nested too deep
+fn check_expansion<E>(section: &Section_Job, scope: Scope, cfg: Config) -> Result<(), Gen_Error<E>>
+{
+  let Section_Parts{identifier, arguments, ..} = section.parts;
+  let Scope{ancestors, expanded_from, ..} = scope;
+  // Generated code may contain sections which generate themselves again
+  if let Some(first) = expanded_from.iter().position(|&ancestor| ancestor == (identifier, arguments.source))
+  {
+    let cycle = expanded_from[first..].iter().map(|&(identifier, _)| identifier).chain([identifier]).map(str::to_owned).collect();
+    return Err(Gen_Error::EXPANSION_CYCLE{location: section.location.clone(), identifier: identifier.to_string(), cycle});
+  }
+  if ancestors.len() > cfg.max_nesting_depth as usize
+  {
+    return Err(Gen_Error::NESTING_TOO_DEEP{location: section.location.clone(), identifier: identifier.to_string(), max_depth: cfg.max_nesting_depth});
+  }
+  Ok(())
+}
+
+/// The code of the section without the indentation of its markers
+fn unindented_code<E>(section: &Section_Job, cfg: Config) -> Result<String, Gen_Error<E>>
+{
+  let Section_Parts{identifier, code, begin, inline, ..} = section.parts;
+  match inline
+  {
+    // Indentation doesn't apply within a line
+    true => Ok(code.to_string()),
+    false => begin.indentation.unindent_str(code, cfg.tab_width).map_err(|error| Gen_Error::UNINDENT_ERROR{
+      location: Location::new(section.path, section.begin_line() + error.line(), error.column()),
+      identifier: identifier.to_string(),
+      error,
+    }),
+  }
+}
+
+/// How the code of a section relates to its stored checksum, see
+/// [`check_section`]
+struct Checked<'a>
+{
+  hand_edit: Option<Hand_Edit<'a>>,
+  /// The reason hand edits of the section are reported with
+  hand_edit_reason: Stale_Reason,
+  /// Why the section is rewritten, if the code stays the same
+  unchanged_reason: Option<Stale_Reason>,
+}
+
+/// Verifies the checksum of the unindented `old_code` of a section
+fn check_section<'a, E>(section: &Section_Job<'_, 'a>, old_code: &str, cfg: Config, mode: Mode) -> Result<Checked<'a>, Gen_Error<E>>
+{
+  let Section_Parts{identifier, arguments, checksum: stored_checksum, checksum_algorithm: stored_algorithm, children, ..} = section.parts;
+  let checksum_len = cfg.checksum_algorithm.stored_len(cfg.checksum_bytes_to_store);
+  let verify_algorithm = stored_algorithm.unwrap_or_default();
+  let key_required = (verify_algorithm.is_keyed() && !stored_checksum.is_empty()) || (cfg.checksum_algorithm.is_keyed() && checksum_len > 0);
+  if key_required && cfg.checksum_key.is_none()
+  {
+    return Err(Gen_Error::MISSING_CHECKSUM_KEY{location: section.location.clone(), identifier: identifier.to_string()});
+  }
+
+  let checksum_length_changed = checksum_len != stored_checksum.len();
+  let checksum_algorithm_changed = !stored_checksum.is_empty() && checksum_len > 0 && verify_algorithm != cfg.checksum_algorithm;
+  let mac_required = cfg.checksum_algorithm.is_keyed() && mode != Mode::MIGRATE;
+  let hand_edit_reason = match verify_algorithm.is_keyed() || mac_required
+  {
+    true => Stale_Reason::INVALID_MAC,
+    false => Stale_Reason::CHECKSUM_MISMATCH,
+  };
+  // The content of user regions is handwritten and not covered by the checksum
+  let old_checksum_code = checksum_code(old_code, cfg).map_err(|error| section.user_region_error(error))?;
+  let verify = |covers_identifier| check_code_checksum(&checksum_input(&old_checksum_code, identifier, arguments, covers_identifier), stored_checksum, verify_algorithm, cfg.checksum_key);
+  let (verified, checksum_mode_changed) = match verify(cfg.checksum_covers_identifier)
+  {
+    // Accepts checksums written with the other mode, so switching modes migrates the file
+    Err(actual) if mode == Mode::MIGRATE => match verify(!cfg.checksum_covers_identifier)
+    {
+      Ok(checksum) => (Ok(checksum), true),
+      Err(_) => (Err(actual), false),
+    },
+    verified => (verified, false),
+  };
+  // Otherwise, a hand edit could be disguised by removing the MAC or by
+  // replacing it with a plain checksum. Only new, empty sections have none.
+  let mac_missing = !verify_algorithm.is_keyed() || stored_checksum.len() < MIN_MAC_LEN;
+  let verified = match verified
+  {
+    Ok(checksum) if mac_required && mac_missing && !(stored_checksum.is_empty() && old_code.is_empty()) => Err(checksum),
+    verified => verified,
+  };
+  // The checksum also covers the nested sections, so the hand edit is
+  // reported against the innermost section not matching its checksum
+  let hand_edit = verified.err().map(|actual| innermost_hand_edit(children, cfg, section.path, section.begin_line()+1).unwrap_or_else(|| Hand_Edit{
+    identifier,
+    lines: section.lines.clone(),
+    location: section.location.clone(),
+    actual,
+  }));
+  let unchanged_reason = match (checksum_algorithm_changed, checksum_mode_changed, checksum_length_changed)
+  {
+    (true, _, _) => Some(Stale_Reason::CHECKSUM_ALGORITHM_CHANGED),
+    (false, true, _) => Some(Stale_Reason::CHECKSUM_MODE_CHANGED),
+    (false, false, true) => Some(Stale_Reason::CHECKSUM_LENGTH_CHANGED),
+    (false, false, false) => None,
+  };
+  Ok(Checked{hand_edit, hand_edit_reason, unchanged_reason})
+}
+
+/// Fails for a hand edit, unless [`Config::on_hand_edit`] resolves it
+fn fail_on_hand_edit<E>(hand_edit: Hand_Edit, reason: Stale_Reason, cfg: Config, inline: bool) -> Result<(), Gen_Error<E>>
+{
+  // An inline section has no room for conflict markers
+  let fails = cfg.on_hand_edit == Hand_Edit_Policy::ERROR || inline && cfg.on_hand_edit == Hand_Edit_Policy::CONFLICT;
+  match (fails, reason)
+  {
+    (false, _) => Ok(()),
+    (true, Stale_Reason::INVALID_MAC) => Err(Gen_Error::INVALID_MAC{location: hand_edit.location, identifier: hand_edit.identifier.to_string()}),
+    (true, _) => Err(Gen_Error::WRONG_CHECKSUM{location: hand_edit.location, identifier: hand_edit.identifier.to_string(), actual: Box::new(hand_edit.actual)}),
+  }
+}
+
+/// Calls the generator for a section, whose current code is `base_code`.
+/// Returns `None` if the code is kept.
+fn run_generator<E>(section: &Section_Job, base_code: &str, scope: Scope, cfg: Config, f: &mut Dyn_Generator<E>, unclaimed: &mut Vec<Unclaimed_Section>) -> Result<Option<String>, Gen_Error<E>>
+{
+  let Section_Parts{identifier, arguments, begin, end, inline, ..} = section.parts;
+  let ctx = Section_Context{
+    identifier,
+    // Copied, as `Arguments` is invariant over the lifetime of the input
+    arguments: Arguments{source: arguments.source, list: arguments.list.iter().copied().collect()},
+    old_code: base_code,
+    indentation: match inline
+    {
+      true => Indentation::default(),
+      false => begin.indentation,
+    },
+    begin: *begin,
+    end: *end,
+    index: section.index,
+    line: section.begin_line(),
+    path: scope.path,
+    parent: scope.ancestors.last().map(|&(parent, _)| parent),
+  };
+  match f(&ctx).map_err(|error| Gen_Error::GENERATOR{location: section.location.clone(), identifier: identifier.to_string(), error})?
+  {
+    Generator_Output::CODE(code) => Ok(Some(code)),
+    Generator_Output::KEEP => Ok(None),
+    Generator_Output::NOT_MINE =>
+    {
+      if cfg.strict
+      {
+        unclaimed.push(Unclaimed_Section{location: section.location.clone(), identifier: identifier.to_string()});
+      }
+      Ok(None)
+    }
+  }
+}
+
+/// Normalizes the code returned by the generator, handles the markers it
+/// contains unless they are `expanded` and restores the user regions of the
+/// unindented `old_code`
+fn prepare_generated_code<E>(new_code: String, section: &Section_Job, old_code: &str, expanded: bool, cfg: Config, indent_style: Option<Indent_Style>) -> Result<String, Gen_Error<E>>
+{
+  let Section_Parts{identifier, begin, inline, ..} = section.parts;
+  let new_code = match inline
+  {
+    false => ensure_tailing_linebreak(normalize_line_endings(new_code)),
+    true =>
+    {
+      let new_code = normalize_line_endings(new_code);
+      match new_code.strip_suffix('\n')
+      {
+        Some(line) if !line.contains('\n') => line.to_owned(),
+        _ if new_code.contains('\n') => return Err(Gen_Error::MULTILINE_INLINE_CODE{location: section.location.clone(), identifier: identifier.to_string()}),
+        _ => new_code,
+      }
+    }
+  };
+  let new_code = match (expanded, cfg.escape_markers)
+  {
+    // The markers are nested sections
+    (true, _) => new_code,
+    (false, true) => escape_markers(&new_code, &cfg.marker_style),
+    (false, false) => match find_marker_line(&new_code, &cfg.marker_style)
+    {
+      Some((line, marker)) => return Err(Gen_Error::FORBIDDEN{location: section.location.clone(), identifier: identifier.to_string(), line, marker: marker.to_owned()}),
+      None => new_code,
+    }
+  };
+  if inline
+  {
+    return Ok(new_code);
+  }
+  let new_code = begin.indentation.adapt_leading_whitespace(new_code, cfg.tab_width, indent_style);
+  if !cfg.user_regions
+  {
+    return Ok(new_code);
+  }
+  match restore_user_regions(&new_code, old_code, &cfg.marker_style)
+  {
+    Ok(new_code) => Ok(new_code),
+    Err(error @ User_Region_Error::MISSING{..}) => Err(section.user_region_error(error)),
+    Err(error) => Err(Gen_Error::GENERATED_USER_REGION{location: section.location.clone(), identifier: identifier.to_string(), error: Box::new(error)}),
+  }
+}
+
+/// The checksum written for `code` into the end marker of a section
+fn checksum_text(code: &str, parts: Section_Parts, cfg: Config) -> String
+{
+  // Keeps the tag of explicitly tagged blake3 checksums
+  let tagged = cfg.checksum_algorithm != Checksum_Algorithm::BLAKE3 || parts.checksum_algorithm == Some(Checksum_Algorithm::BLAKE3);
+  match cfg.checksum_algorithm.stored_len(cfg.checksum_bytes_to_store)
+  {
+    0 => String::new(),
+    len =>
+    {
+      let code = checksum_code(code, cfg).expect("the user regions were checked before");
+      let code = checksum_input(&code, parts.identifier, parts.arguments, cfg.checksum_covers_identifier);
+      let checksum = cfg.checksum_algorithm.checksum_with_key(&code, cfg.checksum_key).expect("the key was checked before");
+      format_checksum(checksum.as_bytes(), len, tagged.then_some(cfg.checksum_algorithm))
+    }
+  }
+}
+
+/// The code written for a hand-edited section with
+/// [`Hand_Edit_Policy::CONFLICT`]
+fn conflict_code<E>(old_code: &str, new_code: String, section: &Section_Job, cfg: Config) -> Result<String, Gen_Error<E>>
+{
+  // An unresolved conflict only gets its generated half refreshed
+  let hand_edit = split_conflict(old_code).map_or(old_code, |(hand_edit, _)| hand_edit);
+  if new_code == hand_edit
+  {
+    return Ok(new_code);
+  }
+  // Both halves would contain the markers, so the next run would find them twice
+  if contains_markers(hand_edit, cfg) || contains_markers(&new_code, cfg)
+  {
+    return Err(Gen_Error::CONFLICT_WITH_MARKERS{location: section.location.clone(), identifier: section.parts.identifier.to_string()});
+  }
+  Ok(format!("{CONFLICT_HAND_EDITED}{hand_edit}{CONFLICT_SEPARATOR}{new_code}{CONFLICT_GENERATED}"))
+}
+
+/// Writes the section with its unindented `code`, without the final line break
+fn write_section(out: &mut String, parts: Section_Parts, code: &str, end_name: &str, checksum: &str, cfg: Config, nl: Line_Ending)
+{
+  let Section_Parts{identifier, arguments, begin, end, inline, ..} = parts;
+  match (inline, cfg.preserve_marker_lines)
+  {
+    (true, true) => cfg.marker_style.copy_inline_line(out, begin, identifier, code, end, checksum),
+    (true, false) => cfg.marker_style.write_inline_line(out, &Inline_Line{begin, identifier, arguments, code, end, end_name, checksum}),
+    (false, preserve_marker_lines) => match preserve_marker_lines
+    {
+      true => cfg.marker_style.copy_begin_line(out, begin),
+      false => cfg.marker_style.write_begin_line(out, begin, identifier, arguments),
+    }.and_then(|()| {
+      *out += nl.as_str();
+      *out += nl.apply(begin.indentation.indent_str(code)).as_str();
+      match preserve_marker_lines
+      {
+        true => cfg.marker_style.copy_end_line(out, end, identifier, checksum),
+        false => cfg.marker_style.write_end_line(out, &Marker{indentation: begin.indentation, ..*end}, end_name, checksum),
+      }
+    }),
+  }.expect("writing to a String can't fail");
+}
+
+/// The status reported for a section rewritten for `reason`. `kept` tells
+/// whether the generator kept the code.
+fn section_status(reason: Option<Stale_Reason>, kept: bool, cfg: Config) -> Section_Status
+{
+  match reason
+  {
+    None if kept => Section_Status::KEPT,
+    None => Section_Status::UNCHANGED,
+    Some(reason) if reason.is_hand_edit() && cfg.on_hand_edit == Hand_Edit_Policy::OVERWRITE => Section_Status::OVERWRITTEN,
+    Some(reason) if reason == Stale_Reason::CONTENT_CHANGED || reason.is_hand_edit() => Section_Status::REGENERATED,
+    Some(_) => Section_Status::CHECKSUM_UPDATED,
+  }
+}
+
+/// Generates the sections nested in the unindented `code` depth first and
+/// collects the identifiers and arguments of the sections generated in
+/// `regenerated`.
+fn generate_depth_first<E>(code: &str, cfg: Config, scope: Scope, mode: Mode, f: &mut Dyn_Generator<E>, regenerated: &mut Vec<(String, String)>) -> Result<Generated, Gen_Error<E>>
+{
+  generate_nested(code, cfg, scope, mode, &mut |ctx| {
+    regenerated.push((ctx.identifier.to_owned(), ctx.arguments.source.to_owned()));
+    f(ctx)
+  })
+}
+
+/// Generates the sections nested in the unindented `code` of a section. The
+/// code is returned as it is, if no nested section changed.
+fn generate_nested<E>(code: &str, cfg: Config, scope: Scope, mode: Mode, f: &mut Dyn_Generator<E>) -> Result<Generated, Gen_Error<E>>
+{
+  let mut generated = generate_sections(code, cfg, scope, mode, f)?;
+  if generated.stale.is_empty()
+  {
+    generated.code = code.to_owned();
+  }
+  Ok(generated)
+}
+
+/// Generates the sections nested in the `skeleton` of a section, see
+/// [`carry_nested_code`].
+///
+/// `regenerated` lists the identifiers and arguments of the sections of the
+/// old code generated depth first already, which are kept.
+fn generate_nested_skeleton<E>(skeleton: &str, regenerated: &[(String, String)], cfg: Config, scope: Scope, mode: Mode, f: &mut Dyn_Generator<E>) -> Result<Generated, Gen_Error<E>>
+{
+  generate_nested(skeleton, cfg, scope, mode, &mut |ctx| match regenerated.iter().any(|(identifier, arguments)| identifier == ctx.identifier && arguments == ctx.arguments.source)
+  {
+    true => Ok(Generator_Output::KEEP),
+    false => f(ctx),
+  })
+}
+
+/// The reports of the sections nested in a section whose begin marker moved
+/// from `begin_line` to `new_begin_line`.
+///
+/// `reports` are the ones of [`generate_nested_skeleton`], whose old lines
+/// are counted in the new code. The sections it kept as they were generated
+/// depth first already get the status and old lines of `depth_first`.
+fn merge_nested_reports(reports: Vec<Section_Report>, mut depth_first: Vec<Section_Report>, begin_line: usize, new_begin_line: usize) -> Vec<Section_Report>
+{
+  reports.into_iter().map(|report| {
+    let old_lines = report.old_lines.start() - new_begin_line + begin_line ..= report.old_lines.end() - new_begin_line + begin_line;
+    let generated = depth_first.iter().position(|generated| generated.identifier == report.identifier).filter(|_| report.status == Section_Status::KEPT);
+    match generated.map(|i| depth_first.remove(i))
+    {
+      Some(generated) => Section_Report{new_lines: report.new_lines, new_checksum: report.new_checksum, ..generated},
+      None => Section_Report{old_lines, ..report},
+    }
+  }).collect()
+}
+
+/// A hand edit of a section, see [`innermost_hand_edit`]
+struct Hand_Edit<'a>
+{
+  identifier: &'a str,
+  /// 1-based line numbers of the begin and end marker
+  lines: RangeInclusive<usize>,
+  location: Location,
+  actual: Checksum,
+}
+
+/// The innermost of the nested `sections` whose code doesn't match its
+/// checksum, if any. `sections` start at the 1-based line `first_line`.
+fn innermost_hand_edit<'a>(sections: &[Section<'a>], cfg: Config, path: Option<&Path>, first_line: usize) -> Option<Hand_Edit<'a>>
+{
+  let mut line = first_line;
+  for section in sections
+  {
+    let begin_line = line;
+    line += section.line_count();
+    let Section_Parts{identifier, arguments, code, checksum, checksum_algorithm, begin, children, inline, ..} = match section.parts()
+    {
+      Some(parts) => parts,
+      None => continue,
+    };
+
+    if let Some(hand_edit) = innermost_hand_edit(children, cfg, path, begin_line+1)
+    {
+      return Some(hand_edit);
+    }
+    let algorithm = checksum_algorithm.unwrap_or_default();
+    if checksum.is_empty() || (algorithm.is_keyed() && cfg.checksum_key.is_none())
+    {
+      continue;
+    }
+    let code = match inline
+    {
+      true => Some(code.to_string()),
+      false => begin.indentation.unindent_str(code, cfg.tab_width).ok(),
+    };
+    let code = code.as_deref().and_then(|code| checksum_code(code, cfg).ok());
+    if let Some(Err(actual)) = code.map(|code| check_code_checksum(&checksum_input(&code, identifier, arguments, cfg.checksum_covers_identifier), checksum, algorithm, cfg.checksum_key))
+    {
+      return Some(Hand_Edit{identifier, lines: begin_line..=line-1, location: Location::new(path, begin_line, begin.column()), actual});
+    }
+  }
+  None
+}
+
+/// Replaces the code of the empty sections in `code` by the code of the
+/// section with the same identifier and arguments in `old_code`, if any.
+///
+/// `scope` is the one of `code`, while `old_code` starts at the 1-based line
+/// `old_first_line`.
+fn carry_nested_code<E>(code: &str, old_code: &str, old_first_line: usize, scope: Scope, cfg: Config, mode: Mode) -> Result<String, Gen_Error<E>>
+{
+  use Section::*;
+  let nl = Line_Ending::detect(code);
+  let named_end_markers = named_end_markers(cfg, mode);
+  let old_sections = find_sections(old_code, &cfg.marker_style, true, named_end_markers).map_err(|e| e.with_path(scope.path).with_line_offset(old_first_line-1))?;
+  let mut line = old_first_line;
+  let mut old_sections : Vec<(usize, &Section)> = old_sections.iter().filter_map(|section| {
+    let begin_line = line;
+    line += section.line_count();
+    matches!(section, CODEGEN{..}).then_some((begin_line, section))
+  }).collect();
+
+  let mut carried = String::with_capacity(code.len() + old_code.len());
+  for section in find_sections(code, &cfg.marker_style, true, named_end_markers).map_err(|e| e.with_path(scope.path).with_line_offset(scope.first_line-1))?.iter()
+  {
+    match section
+    {
+      HANDWRITTEN(code) => carried += code,
+      CODEGEN{identifier, arguments, code, begin, end, ..} =>
+      {
+        carried += &format!("{begin}{nl}");
+        let old = old_sections.iter().position(|(_, old)| matches!(old, CODEGEN{identifier: old_identifier, arguments: old_arguments, ..} if old_identifier == identifier && old_arguments.source == arguments.source)).filter(|_| code.is_empty());
+        match old.map(|i| old_sections.remove(i))
         {
-          write!(&mut generated, "{checksum} ", checksum=&new_checksum.to_hex()[0..2*cfg.checksum_bytes_to_store as usize])?;
+          Some((old_begin_line, CODEGEN{code: old_code, begin: old_begin, end: old_end, ..})) =>
+          {
+            let old_code = old_begin.indentation.unindent_str(old_code, cfg.tab_width).map_err(|error| Gen_Error::UNINDENT_ERROR{
+              location: Location::new(scope.path, old_begin_line + error.line(), error.column()),
+              identifier: identifier.to_string(),
+              error,
+            })?;
+            let old_code = nl.apply(begin.indentation.indent_str(&old_code));
+            carried += &format!("{old_code}{old_end}{nl}");
+          }
+          _ => carried += &format!("{code}{end}{nl}"),
         }
-        write!(&mut generated, ">>{after}\n", after=end.after_marker)?;
-
-        changed = changed || new_checksum != old_checksum;
       }
+      INLINE{code, begin, end, ..} => carried += &format!("{begin}{code}{end}{nl}"),
     }
   }
+  Ok(carried)
+}
+
+/// Whether a single untagged token in an end marker is read as its name, see
+/// [`Config::named_end_markers`]. [`migrate_checksums`] reads it as checksum,
+/// so end markers written without the setting get their names.
+fn named_end_markers(cfg: Config, mode: Mode) -> bool
+{
+  cfg.named_end_markers && mode != Mode::MIGRATE
+}
+
+/// The name written into the end marker `end` of the section `identifier`.
+/// Names written by hand are kept.
+fn end_name<'a>(cfg: Config, identifier: &'a str, end: &Marker) -> &'a str
+{
+  match cfg.named_end_markers || end_marker_name(end.source, identifier, &cfg.marker_style) == Some(identifier)
+  {
+    true => identifier,
+    false => "",
+  }
+}
+
+/// Whether `code` contains nested sections or user regions
+fn contains_markers(code: &str, cfg: Config) -> bool
+{
+  let user_regions = || find_user_regions(code, &cfg.marker_style).map_or(true, |regions| !regions.is_empty());
+  find_marker_line(code, &cfg.marker_style).is_some() || cfg.user_regions && user_regions()
+}
+
+/// The code covered by the checksum of a section. With
+/// [`Config::user_regions`], the content of the user regions is left out.
+fn checksum_code<'c>(code: &'c str, cfg: Config) -> Result<Cow<'c, str>, User_Region_Error>
+{
+  match cfg.user_regions
+  {
+    true => strip_user_regions(code, &cfg.marker_style),
+    false => Ok(Cow::Borrowed(code)),
+  }
+}
+
+const CONFLICT_HAND_EDITED : &str = "<<<<<<< hand-edited\n";
+const CONFLICT_SEPARATOR : &str = "=======\n";
+const CONFLICT_GENERATED : &str = ">>>>>>> generated\n";
+
+/// Splits a conflict written by [`Hand_Edit_Policy::CONFLICT`] into the hand
+/// edit and the generated code
+fn split_conflict(code: &str) -> Option<(&str, &str)>
+{
+  let conflict = code.strip_prefix(CONFLICT_HAND_EDITED)?.strip_suffix(CONFLICT_GENERATED)?;
+  let separator = match conflict.starts_with(CONFLICT_SEPARATOR)
+  {
+    true => 0,
+    false => conflict.find(&format!("\n{CONFLICT_SEPARATOR}"))? + 1,
+  };
+  Some((&conflict[..separator], &conflict[separator+CONFLICT_SEPARATOR.len()..]))
+}
+
+fn count_lines(code: &str) -> usize
+{
+  code.bytes().filter(|&c| c == b'\n').count()
+}
+
+/// Returns the actual checksum of `code` as error, if it doesn't match
+fn check_code_checksum(code: &[u8], loaded_checksam: &ArrayVec<u8, 32>, algorithm: Checksum_Algorithm, key: Option<&Checksum_Key>) -> Result<Checksum, Checksum>
+{
+  let actual_checksum = algorithm.checksum_with_key(code, key).expect("the key was checked before");
+  if !actual_checksum.matches(loaded_checksam.as_slice())
+  {
+    return Err(actual_checksum);
+  }
+
+  return Ok(actual_checksum);
+}
+
+/// The bytes the checksum is computed of.
+///
+/// With `covers_identifier`, the code is prefixed by the length-prefixed
+/// identifier and arguments, so moving code between sections changes the
+/// checksum.
+fn checksum_input<'a>(code: &'a str, identifier: &str, arguments: &Arguments, covers_identifier: bool) -> Cow<'a, [u8]>
+{
+  if !covers_identifier
+  {
+    return Cow::Borrowed(code.as_bytes());
+  }
 
-  return if changed {Ok(Some(generated))} else {Ok(None)};
+  let mut input = Vec::with_capacity(code.len() + 64);
+  let mut push = |text: &str| {
+    input.extend_from_slice(&(text.len() as u64).to_le_bytes());
+    input.extend_from_slice(text.as_bytes());
+  };
+  push(identifier);
+  for argument in arguments.list.iter()
+  {
+    push(argument.name.unwrap_or(""));
+    push(argument.value);
+  }
+  input.extend_from_slice(&(arguments.list.len() as u64).to_le_bytes());
+  input.extend_from_slice(code.as_bytes());
+  Cow::Owned(input)
 }
 
-fn check_code_checksum(code: &str, loaded_checksam: &ArrayVec<u8, 32>) -> Result<blake3::Hash>
+/// Formats the first `len` bytes of `checksum` as hex, prefixed by the tag of `algorithm`
+fn format_checksum(checksum: &[u8], len: usize, algorithm: Option<Checksum_Algorithm>) -> String
 {
-  let actual_hashsum = blake3::hash(code.as_bytes());
-  if &actual_hashsum.as_bytes()[..loaded_checksam.len()] != loaded_checksam.as_slice()
+  if len == 0
   {
-    return Err(Gen_Error::WRONG_CHECKSUM(actual_hashsum));
+    return String::new();
   }
-
-  return Ok(actual_hashsum);
+  let tag = algorithm.map_or(String::new(), |algorithm| format!("{}:", algorithm.tag()));
+  checksum[..len].iter().fold(tag, |text, byte| text + &format!("{byte:02x}"))
 }
 
 pub type Result<T=(), E=Gen_Error> = std::result::Result<T, E>;
 
 #[derive(Debug, Error, PartialEq, Eq)]
-pub enum Gen_Error
+pub enum Gen_Error<E=std::fmt::Error>
 {
   #[error("{0}")]
   FIND(#[from] crate::parse_file::Parse_Error),
-  #[error("fmt error: {0}")]
-  FMT(#[from] std::fmt::Error),
-  #[error("wrong blake3 checksum. Was the code modified in between?\nActual blake3 checksum: {0}")]
-  WRONG_CHECKSUM(blake3::Hash),
-  #[error("The code generating function modified code outside the code section")]
-  FORBIDDEN,
-  #[error("The old code has a smaller indentation than the marker")]
-  UNINDENT_ERROR(#[from] crate::indentation::Unindent_Error),
+  #[error("{location}: the generator failed for section `{identifier}`: {error}")]
+  GENERATOR{location: Location, identifier: String, error: E},
+  #[error("{location}: section `{identifier}` was hand-edited (checksum mismatch)")]
+  WRONG_CHECKSUM{location: Location, identifier: String, actual: Box<Checksum>},
+  #[error("{location}: section `{identifier}` has an invalid MAC (edited without the checksum key)")]
+  INVALID_MAC{location: Location, identifier: String},
+  #[error("{location}: section `{identifier}` requires a checksum key")]
+  MISSING_CHECKSUM_KEY{location: Location, identifier: String},
+  #[error("{location}: the generated code for section `{identifier}` contains the marker line `{marker}` (line {line} of the generated code)")]
+  FORBIDDEN{location: Location, identifier: String, line: usize, marker: String},
+  /// A user region of the old code is broken or would be dropped. The
+  /// location points at its marker line.
+  #[error("{location}: section `{identifier}` has {error}")]
+  USER_REGION{location: Location, identifier: String, error: Box<User_Region_Error>},
+  #[error("{location}: the generated code for section `{identifier}` has {error} (line {} of the generated code)", .error.line())]
+  GENERATED_USER_REGION{location: Location, identifier: String, error: Box<User_Region_Error>},
+  /// A hand-edited section contains nested sections or user regions, which
+  /// [`Hand_Edit_Policy::CONFLICT`] would write twice
+  #[error("{location}: section `{identifier}` was hand-edited, but can't get conflict markers, as it contains nested sections or user regions")]
+  CONFLICT_WITH_MARKERS{location: Location, identifier: String},
+  #[error("{location}: the generated code for the inline section `{identifier}` spans multiple lines")]
+  MULTILINE_INLINE_CODE{location: Location, identifier: String},
+  #[error("{location}: section `{identifier}` has a smaller indentation than its marker: {error}")]
+  UNINDENT_ERROR{location: Location, identifier: String, error: crate::indentation::Unindent_Error},
+  #[error("{location}: section `{identifier}` is nested in more than {max_depth} sections")]
+  NESTING_TOO_DEEP{location: Location, identifier: String, max_depth: u8},
+  /// The generated code of a section contains a section with the same
+  /// identifier and arguments as it or a section it was generated by
+  #[error("{location}: section `{identifier}` expands into itself: {}", .cycle.join(" -> "))]
+  EXPANSION_CYCLE{location: Location, identifier: String, cycle: Vec<String>},
+  #[error("no generator claimed the sections {}", list_unclaimed(.0))]
+  UNCLAIMED_SECTIONS(Vec<Unclaimed_Section>),
+}
+
+/// A section the generator returned [`Generator_Output::NOT_MINE`] for in
+/// [`Config::strict`] mode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unclaimed_Section
+{
+  pub location: Location,
+  pub identifier: String,
+}
+
+fn list_unclaimed(sections: &[Unclaimed_Section]) -> String
+{
+  sections.iter().map(|section| format!("`{}` ({})", section.identifier, section.location)).collect::<Vec<_>>().join(", ")
 }
 
-impl Config
+impl Config<'static>
 {
+  pub const DEFAULT : Self = Config{
+    checksum_bytes_to_store: 0,
+    tab_width: 0,
+    marker_style: Marker_Style::DEFAULT,
+    escape_markers: false,
+    on_hand_edit: Hand_Edit_Policy::ERROR,
+    checksum_algorithm: Checksum_Algorithm::BLAKE3,
+    checksum_key: None,
+    checksum_covers_identifier: false,
+    preserve_marker_lines: false,
+    strict: false,
+    user_regions: false,
+    nesting: Nesting::FORBIDDEN,
+    expand_generated_sections: false,
+    max_nesting_depth: 16,
+    named_end_markers: false,
+  };
+}
+
+impl Config<'_>
+{
+  /// [`Config::checksum_bytes_to_store`] may exceed the output length of the
+  /// algorithm, as it's clamped.
   pub fn is_valid(&self) -> bool
   {
-    self.checksum_bytes_to_store <= blake3::KEY_LEN as u8
+    (!self.checksum_algorithm.is_keyed() || self.checksum_key.is_some())
+    && self.marker_style.is_valid()
+  }
+}
+
+impl Default for Config<'_>
+{
+  fn default() -> Self
+  {
+    Config::DEFAULT
   }
 }
 
@@ -106,29 +1139,29 @@ mod test
 {
   use super::*;
 
-  const CFG : Config = Config{checksum_bytes_to_store: 0};
+  const CFG : Config = Config{checksum_bytes_to_store: 0, .. Config::DEFAULT};
 
   #[test]
   fn test_trivial()
   {
-    assert_eq!(generate("", CFG, |_| Ok(Some("abc".to_owned()))).unwrap_display(), None);
-    assert_eq!(generate("xyz", CFG, |_| Ok(Some("abc".to_owned()))).unwrap_display(), None);
+    assert_eq!(generate("", CFG, |_: &str| Some("abc".to_owned())).unwrap_display(), None);
+    assert_eq!(generate("xyz", CFG, |_: &str| Some("abc".to_owned())).unwrap_display(), None);
   }
 
   #[test]
   fn test_simple_replace()
   {
-    assert_eq!(generate("<< codegen foo >>\nxyz\n<< /codegen >>", CFG, |_| Ok(Some("xyz".to_owned())) ).unwrap_display(), None);
-    assert_eq!(generate("<< codegen foo >>\nxyz\n<< /codegen >>", CFG, |_| Ok(Some("uvw".to_owned())) ).unwrap_display(), Some("<< codegen foo >>\nuvw\n<< /codegen >>\n".to_owned()));
-    assert_eq!(generate("<< codegen foo >>\nremove me\n<< /codegen >>", CFG, |_| Ok(Some("".to_owned()))).unwrap_display(), Some("<< codegen foo >>\n<< /codegen >>\n".to_owned()));
-    assert_eq!(generate("abc\ndefg<< codegen foo >>hijk\nxyz\nlmnop<< /codegen >>qrst\nuvw", CFG, |_| Ok(Some("uvw".to_owned())) ).unwrap_display(), Some("abc\ndefg<< codegen foo >>hijk\nuvw\nlmnop<< /codegen >>qrst\nuvw".to_owned()));
+    assert_eq!(generate("<< codegen foo >>\nxyz\n<< /codegen >>", CFG, |_: &str| Some("xyz".to_owned()) ).unwrap_display(), None);
+    assert_eq!(generate("<< codegen foo >>\nxyz\n<< /codegen >>", CFG, |_: &str| Some("uvw".to_owned()) ).unwrap_display(), Some("<< codegen foo >>\nuvw\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("<< codegen foo >>\nremove me\n<< /codegen >>", CFG, |_: &str| Some("".to_owned())).unwrap_display(), Some("<< codegen foo >>\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("abc\ndefg<< codegen foo >>hijk\nxyz\nlmnop<< /codegen >>qrst\nuvw", CFG, |_: &str| Some("uvw".to_owned()) ).unwrap_display(), Some("abc\ndefg<< codegen foo >>hijk\nuvw\nlmnop<< /codegen >>qrst\nuvw".to_owned()));
   }
 
   #[test]
   fn test_use_identifier()
   {
     assert_eq!(generate("<< codegen answer >>\n<< /codegen >>\n<< codegen finestructure_constant >>\n<< /codegen >>", CFG,
-      |i|
+      |i: &str|
       {
         let code = match i
         {
@@ -136,17 +1169,19 @@ mod test
         "finestructure_constant" => "137",
         _ => unreachable!("{i}"),
         };
-        Ok(Some(code.to_owned()))
-      }).unwrap_display(), Some("<< codegen answer >>\n42\n<< /codegen >>\n<< codegen finestructure_constant >>\n137\n<< /codegen >>\n".to_owned()));
+        Some(code.to_owned())
+      }).unwrap_display(), Some("<< codegen answer >>\n42\n<< /codegen >>\n<< codegen finestructure_constant >>\n137\n<< /codegen >>".to_owned()));
   }
   
   #[test]
   fn test_check_checksum()
   {
-    assert_eq!(check_code_checksum("42", &ArrayVec::new()), Ok(blake3::hash(b"42")));
-    assert_eq!(check_code_checksum("42", &blake3::hash(b"42").as_bytes().iter().copied().collect()), Ok(blake3::hash(b"42")));
-    assert_eq!(check_code_checksum("42", &blake3::hash(b"42").as_bytes()[0..4].iter().copied().collect()), Ok(blake3::hash(b"42")));
-    assert_eq!(check_code_checksum("42", &blake3::hash(b"42").as_bytes()[1..5].iter().copied().collect()), Err(Gen_Error::WRONG_CHECKSUM(blake3::hash(b"42"))));
+    let hash = Checksum_Algorithm::BLAKE3.checksum(b"42");
+    assert_eq!(check_code_checksum(b"42", &ArrayVec::new(), Checksum_Algorithm::BLAKE3, None), Ok(hash));
+    assert_eq!(check_code_checksum(b"42", &blake3::hash(b"42").as_bytes().iter().copied().collect(), Checksum_Algorithm::BLAKE3, None), Ok(hash));
+    assert_eq!(check_code_checksum(b"42", &blake3::hash(b"42").as_bytes()[0..4].iter().copied().collect(), Checksum_Algorithm::BLAKE3, None), Ok(hash));
+    assert_eq!(check_code_checksum(b"42", &blake3::hash(b"42").as_bytes()[1..5].iter().copied().collect(), Checksum_Algorithm::BLAKE3, None), Err(hash));
+    assert_eq!(check_code_checksum(b"42", &blake3::hash(b"42").as_bytes()[0..4].iter().copied().collect(), Checksum_Algorithm::SHA256, None), Err(Checksum_Algorithm::SHA256.checksum(b"42")));
   }
 
   #[test]
@@ -173,26 +1208,26 @@ mod test
 
     // differenet lengths
     assert_eq!(generate("<< codegen empty >>\n<< /codegen >>", CKSM_0, gen).unwrap_display(), None);
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13 >>", CKSM_0, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen >>\n".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13 >>", CKSM_0, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen >>".to_owned()));
     assert_eq!(generate("<< codegen empty >>\n<< /codegen af13 >>", CKSM_2, gen).unwrap_display(), None);
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen >>", CKSM_2, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af13 >>\n".to_owned()));
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13>>", CKSM_4, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9 >>\n".to_owned()));
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13>>", CKSM_5, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9f5 >>\n".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen >>", CKSM_2, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af13 >>".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13>>", CKSM_4, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9 >>".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13>>", CKSM_5, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9f5 >>".to_owned()));
     
     // replace content
-    assert_eq!(generate("<< codegen 42 >>\n<< /codegen af1349b9f5>>", CKSM_5, gen).unwrap_display(), Some("<< codegen 42 >>\n42\n<< /codegen a16072b1b0 >>\n".to_owned()));
-    assert_eq!(generate("<< codegen empty >>\n42\n<< /codegen a16072b1b0>>", CKSM_5, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9f5 >>\n".to_owned()));
+    assert_eq!(generate("<< codegen 42 >>\n<< /codegen af1349b9f5>>", CKSM_5, gen).unwrap_display(), Some("<< codegen 42 >>\n42\n<< /codegen a16072b1b0 >>".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n42\n<< /codegen a16072b1b0>>", CKSM_5, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9f5 >>".to_owned()));
     
     // newline handling
-    assert_eq!(generate("<< codegen 42_newline >>\n42\n<< /codegen a16072b1>>", CKSM_5, gen).unwrap_display(), Some("<< codegen 42_newline >>\n42\n<< /codegen a16072b1b0 >>\n".to_owned()));
-    assert_eq!(generate("<< codegen newline >>\n<< /codegen af1349b9f5>>", CKSM_5, gen).unwrap_display(), Some("<< codegen newline >>\n\n<< /codegen 295192ea1e >>\n".to_owned()));
+    assert_eq!(generate("<< codegen 42_newline >>\n42\n<< /codegen a16072b1>>", CKSM_5, gen).unwrap_display(), Some("<< codegen 42_newline >>\n42\n<< /codegen a16072b1b0 >>".to_owned()));
+    assert_eq!(generate("<< codegen newline >>\n<< /codegen af1349b9f5>>", CKSM_5, gen).unwrap_display(), Some("<< codegen newline >>\n\n<< /codegen 295192ea1e >>".to_owned()));
 
     // bug: dirty flag overwritten:
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13 >>\n<< codegen empty >>\n<< /codegen >>", CKSM_0, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen >>\n<< codegen empty >>\n<< /codegen >>\n".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13 >>\n<< codegen empty >>\n<< /codegen >>", CKSM_0, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen >>\n<< codegen empty >>\n<< /codegen >>".to_owned()));
 
     // max length
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13>>", CKSM_MAX, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262 >>\n".to_owned()));
-    assert_eq!(generate("<< codegen empty >>\n<< /codegen af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262>>", CKSM_2, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af13 >>\n".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen af13>>", CKSM_MAX, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262 >>".to_owned()));
+    assert_eq!(generate("<< codegen empty >>\n<< /codegen af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262>>", CKSM_2, gen).unwrap_display(), Some("<< codegen empty >>\n<< /codegen af13 >>".to_owned()));
   }
   
   #[test]
@@ -208,10 +1243,768 @@ mod test
       Ok(Some(x.into()))
     }
 
-    assert_eq!(generate("<< codegen x >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("<< codegen x >>\n42\n137\n1337\n<< /codegen >>\n".to_owned()));
-    assert_eq!(generate("  << codegen x >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("  << codegen x >>\n  42\n  137\n  1337\n  << /codegen >>\n".to_owned()));
+    assert_eq!(generate("<< codegen x >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("<< codegen x >>\n42\n137\n1337\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("  << codegen x >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("  << codegen x >>\n  42\n  137\n  1337\n  << /codegen >>".to_owned()));
   }
   
+  #[test]
+  fn test_tab_indentation()
+  {
+    fn gen(n: &str) -> Fmt_Result
+    {
+      let x = match n
+      {
+        "x" => "all:\n    echo 42\n\techo 137",
+        n => todo!("{n}"),
+      };
+      Ok(Some(x.into()))
+    }
+
+    const TABS : Config = Config{tab_width: 4, .. CFG};
+
+    assert_eq!(generate("\t# << codegen x >>\n\t# << /codegen >>", CFG, gen).unwrap_display(), Some("\t# << codegen x >>\n\tall:\n\t    echo 42\n\t\techo 137\n\t# << /codegen >>".to_owned()));
+    assert_eq!(generate("\t# << codegen x >>\n\t# << /codegen >>", TABS, gen).unwrap_display(), Some("\t# << codegen x >>\n\tall:\n\t\techo 42\n\t\techo 137\n\t# << /codegen >>".to_owned()));
+    assert_eq!(generate("  # << codegen x >>\n  # << /codegen >>", TABS, gen).unwrap_display(), Some("  # << codegen x >>\n  all:\n      echo 42\n      echo 137\n  # << /codegen >>".to_owned()));
+
+    // A marker in column 0, like in a Makefile, takes the style of the file
+    assert_eq!(generate("# << codegen x >>\n# << /codegen >>\nclean:\n\trm -f x\n", TABS, gen).unwrap_display(), Some("# << codegen x >>\nall:\n\techo 42\n\techo 137\n# << /codegen >>\nclean:\n\trm -f x\n".to_owned()));
+    assert_eq!(generate("# << codegen x >>\n# << /codegen >>\n", TABS, gen).unwrap_display(), Some("# << codegen x >>\nall:\n    echo 42\n\techo 137\n# << /codegen >>\n".to_owned()));
+
+    // tab indented code is kept when regenerating
+    assert_eq!(generate("\t# << codegen x >>\n\tall:\n\t\techo 42\n\t\techo 137\n\t# << /codegen >>\n", TABS, gen).unwrap_display(), None);
+
+    // The indentation of the code may mix tabs and spaces differently than the markers
+    let generated = generate("  \t# << codegen x >>\n  \t# << /codegen >>\n", Config{checksum_bytes_to_store: 2, ..TABS}, gen).unwrap_display().unwrap();
+    assert_eq!(generated, format!("  \t# << codegen x >>\n  \tall:\n  \t\techo 42\n  \t\techo 137\n  \t# << /codegen {} >>\n", &blake3::hash(b"all:\n\techo 42\n\techo 137\n").to_hex()[..4]));
+    let retabbed = generated.replace("  \tall", "\tall").replace("  \t\techo 42", "    \techo 42");
+    assert_eq!(generate(&retabbed, Config{checksum_bytes_to_store: 2, ..TABS}, gen).unwrap_display(), None);
+    assert!(matches!(generate(&retabbed, Config{checksum_bytes_to_store: 2, ..CFG}, gen), Err(Gen_Error::UNINDENT_ERROR{..})));
+  }
+
+  #[test]
+  fn test_line_endings()
+  {
+    fn gen(n: &str) -> Fmt_Result
+    {
+      let x = match n
+      {
+        "x" => "42\n137",
+        "y" => "42\r\n137\r\n",
+        n => todo!("{n}"),
+      };
+      Ok(Some(x.into()))
+    }
+
+    const CKSM_2 : Config = Config{checksum_bytes_to_store: 2, .. CFG};
+
+    assert_eq!(generate("a\r\n  << codegen x >> b\r\n<< /codegen >> c\r\nd", CKSM_2, gen).unwrap_display(), Some("a\r\n  << codegen x >> b\r\n  42\r\n  137\r\n  << /codegen a5fd >> c\r\nd".to_owned()));
+    assert_eq!(generate("a\n  << codegen y >> b\n<< /codegen >> c\nd", CKSM_2, gen).unwrap_display(), Some("a\n  << codegen y >> b\n  42\n  137\n  << /codegen a5fd >> c\nd".to_owned()));
+
+    // the checksum does not depend on the line ending
+    assert_eq!(generate("<< codegen x >>\r\n42\r\n137\r\n<< /codegen a5fd >>\r\n", CKSM_2, gen).unwrap_display(), None);
+    assert_eq!(generate("<< codegen x >>\n42\n137\n<< /codegen a5fd >>\n", CKSM_2, gen).unwrap_display(), None);
+    assert_eq!(generate("<< codegen x >>\r\n42\r\n137\r\n<< /codegen a5fd >>\r\n", CKSM_2, |_: &str| None).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_marker_style()
+  {
+    const REGION : Config = Config{marker_style: Marker_Style::REGION, checksum_bytes_to_store: 2, .. CFG};
+    const BRACKETS : Config = Config{marker_style: Marker_Style::BRACKETS, .. CFG};
+
+    assert_eq!(generate("  // #region gen:x\n  // #endregion gen\n", REGION, |_: &str| Some("42".to_owned())).unwrap_display(), Some("  // #region gen:x\n  42\n  // #endregion gen a160\n".to_owned()));
+    // The text after the markers is kept byte-for-byte
+    assert_eq!(generate("#region gen:x;\n#endregion gen  // x\n", REGION, |_: &str| Some("42".to_owned())).unwrap_display(), Some("#region gen:x;\n42\n#endregion gen a160  // x\n".to_owned()));
+    // Plain C# regions are handwritten code
+    let csharp = "#region Helpers\nint x;\n#endregion\n#region gen:x\n#endregion gen\n#region Other\n#endregion\n";
+    assert_eq!(generate(csharp, REGION, |_: &str| Some("42".to_owned())).unwrap_display(), Some(csharp.replace("#endregion gen", "42\n#endregion gen a160")));
+    assert_eq!(generate("#region gen:x\n#region Helpers\n#endregion\n#endregion gen\n", Config{checksum_bytes_to_store: 0, ..REGION}, |_: &str| None).unwrap_display(), None);
+    assert_eq!(generate("std::cout << x; // [[[ codegen x ]]]\n// [[[ /codegen ]]]", BRACKETS, |_: &str| Some("std::cout << 42;".to_owned())).unwrap_display(), Some("std::cout << x; // [[[ codegen x ]]]\nstd::cout << 42;\n// [[[ /codegen ]]]".to_owned()));
+    assert_eq!(generate("<< codegen x >>\n<< /codegen >>", BRACKETS, |_: &str| Some("42".to_owned())).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_arguments()
+  {
+    fn gen(n: &str, arguments: &Arguments) -> Fmt_Result
+    {
+      let x = match n
+      {
+        "table" => format!("struct {}{{{}}}", arguments.positional().next().unwrap(), arguments.get("columns").unwrap_or("")),
+        n => todo!("{n}"),
+      };
+      Ok(Some(x))
+    }
+
+    assert_eq!(generate("<< codegen table(users, columns=\"id,name\") >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("<< codegen table(users, columns=\"id,name\") >>\nstruct users{id,name}\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("<< codegen table groups columns=id >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("<< codegen table groups columns=id >>\nstruct groups{id}\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("<< codegen table(users) >>\n<< /codegen >>", CFG, |_: &str| Some("x".to_owned())).unwrap_display(), Some("<< codegen table(users) >>\nx\n<< /codegen >>".to_owned()));
+  }
+
+  #[test]
+  fn test_context()
+  {
+    let input = "a\nb\n  // << codegen x(42) >> foo\n  old\n  // << /codegen >>\nc\n\t<< codegen y >>\n\t<< /codegen >> bar";
+    let mut contexts = vec![];
+    generate(input, CFG, |ctx: &Section_Context| {contexts.push(format!("{:?}", (ctx.identifier, ctx.arguments.source, ctx.old_code, ctx.indentation, ctx.begin.after_marker, ctx.end.after_marker, ctx.index, ctx.line, ctx.path))); None}).unwrap_display();
+
+    assert_eq!(contexts, vec![
+      format!("{:?}", ("x", "(42)", "old\n", Indentation("  "), " foo", "", 0, 3, None::<&Path>)),
+      format!("{:?}", ("y", "", "", Indentation("\t"), "", " bar", 1, 7, None::<&Path>)),
+    ]);
+
+    // merging with the previous content
+    assert_eq!(generate("<< codegen x >>\n1\n<< /codegen >>", CFG, |ctx: &Section_Context| Some(format!("{}2", ctx.old_code))).unwrap_display(), Some("<< codegen x >>\n1\n2\n<< /codegen >>".to_owned()));
+  }
+
+  #[test]
+  fn test_generator_error()
+  {
+    #[derive(Debug, PartialEq, Eq)]
+    struct Missing_Schema(&'static str);
+
+    let gen = |n: &str| match n
+    {
+      "users" => Ok(Some("struct User;".to_owned())),
+      _ => Err(Missing_Schema("schema.sql")),
+    };
+
+    assert_eq!(generate("<< codegen users >>\n<< /codegen >>\n  << codegen groups >>\n<< /codegen >>\n", CFG, gen), Err(Gen_Error::GENERATOR{location: Location::new(None, 3, 3), identifier: "groups".to_owned(), error: Missing_Schema("schema.sql")}));
+    assert_eq!(format!("{}", Gen_Error::GENERATOR{location: Location::new(Some(Path::new("src/foo.rs")), 42, 1), identifier: "groups".to_owned(), error: "missing schema"}), "src/foo.rs:42: the generator failed for section `groups`: missing schema");
+  }
+
+  #[test]
+  fn test_error_location()
+  {
+    let hand_edited = "x\n// << codegen bar >>\nhand edited\n// << /codegen af13 >>\n";
+    let error = generate_file(hand_edited, CFG, Some(Path::new("src/foo.rs")), |_: &str| None).unwrap_err();
+    assert_eq!(error, Gen_Error::WRONG_CHECKSUM{location: Location::new(Some(Path::new("src/foo.rs")), 2, 4), identifier: "bar".to_owned(), actual: Box::new(Checksum_Algorithm::BLAKE3.checksum(b"hand edited\n"))});
+    assert_eq!(error.to_string(), "src/foo.rs:2: section `bar` was hand-edited (checksum mismatch)");
+
+    let bad_indentation = "x\n  << codegen bar >>\n  ok\n bad\n  << /codegen >>\n";
+    let error = generate(bad_indentation, CFG, |_: &str| None).unwrap_err();
+    assert_eq!(error, Gen_Error::UNINDENT_ERROR{location: Location::new(None, 4, 2), identifier: "bar".to_owned(), error: crate::indentation::Unindent_Error::NON_WS_IN_INDENTATION{line: 2, column: 2}});
+
+    let parse_error = generate_file("x\n<< /codegen >>\n", CFG, Some(Path::new("src/foo.rs")), |_: &str| None).unwrap_err();
+    assert_eq!(parse_error.to_string(), "src/foo.rs:2: end marker without a begin marker");
+  }
+
+  #[test]
+  fn test_marker_in_generated_code()
+  {
+    let input = "x\n  << codegen foo >>\n  << /codegen >>\n";
+    let gen = |_: &str| Some("a\n// << /codegen >>\nb".to_owned());
+
+    let error = generate_file(input, CFG, Some(Path::new("src/foo.rs")), gen).unwrap_err();
+    assert_eq!(error, Gen_Error::FORBIDDEN{location: Location::new(Some(Path::new("src/foo.rs")), 2, 3), identifier: "foo".to_owned(), line: 2, marker: "// << /codegen >>".to_owned()});
+    assert_eq!(error.to_string(), "src/foo.rs:2: the generated code for section `foo` contains the marker line `// << /codegen >>` (line 2 of the generated code)");
+
+    const ESCAPE : Config = Config{escape_markers: true, .. CFG};
+    let output = generate(input, ESCAPE, gen).unwrap_display().unwrap();
+    assert_eq!(output, "x\n  << codegen foo >>\n  a\n  // <<\\ /codegen >>\n  b\n  << /codegen >>\n");
+    assert_eq!(generate(output.as_str(), ESCAPE, gen).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_check()
+  {
+    const CKSM_2 : Config = Config{checksum_bytes_to_store: 2, .. CFG};
+    let old = &blake3::hash(b"old\n").to_hex()[..4];
+    let input = format!("x\n<< codegen a >>\nold\n<< /codegen {old} >>\n<< codegen b >>\nsame\n<< /codegen >>\n<< codegen c >>\nedited\n\n<< /codegen {old} >>\n<< codegen d >>\nold\n<< /codegen {old} >>\n");
+    let mut identifiers = vec![];
+    let stale = check(&input, CKSM_2, |i: &str| {
+      identifiers.push(i.to_owned());
+      match i {"a" => Some("new".to_owned()), "b" => Some("same".to_owned()), _ => None}
+    }).unwrap_display();
+
+    assert_eq!(identifiers, ["a", "b", "d"]);
+    assert_eq!(stale, vec![
+      Stale_Section{identifier: "a".to_owned(), lines: 2..=4, reason: Stale_Reason::CONTENT_CHANGED},
+      Stale_Section{identifier: "b".to_owned(), lines: 5..=7, reason: Stale_Reason::CHECKSUM_LENGTH_CHANGED},
+      Stale_Section{identifier: "c".to_owned(), lines: 8..=11, reason: Stale_Reason::CHECKSUM_MISMATCH},
+    ]);
+    assert_eq!(stale[2].to_string(), "lines 8-11: section `c` was hand-edited (checksum mismatch)");
+
+    assert_eq!(check("<< codegen a >>\nold\n<< /codegen >>\n", CFG, |_: &str| Some("old".to_owned())).unwrap_display(), vec![]);
+  }
+
+  #[test]
+  fn test_diff()
+  {
+    let input = "x\n<< codegen foo >>\n1\n<< /codegen >>\ny\n<< codegen bar >>\n<< /codegen >>\nz\n";
+    let patch = diff(input, CFG, |i: &str| match i {"foo" => Some("1\n2".to_owned()), _ => None}).unwrap_display();
+    assert_eq!(patch.unwrap(), "--- input\n+++ input\n@@ -3,2 +3,3 @@ foo\n 1\n+2\n << /codegen >>\n");
+
+    assert_eq!(diff(input, CFG, |_: &str| None).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_hand_edit_policy()
+  {
+    let config = |on_hand_edit| Config{checksum_bytes_to_store: 2, on_hand_edit, .. CFG};
+    let checksum = |code: &str| blake3::hash(code.as_bytes()).to_hex()[..4].to_owned();
+    let input = format!("x\n  << codegen foo >>\n  edited\n  << /codegen {} >>\ny\n", checksum("old\n"));
+    let gen = |_: &str| Some("new".to_owned());
+
+    assert!(matches!(generate(&input, config(Hand_Edit_Policy::ERROR), gen), Err(Gen_Error::WRONG_CHECKSUM{..})));
+    assert_eq!(generate(&input, config(Hand_Edit_Policy::OVERWRITE), gen).unwrap_display(), Some(format!("x\n  << codegen foo >>\n  new\n  << /codegen {} >>\ny\n", checksum("new\n"))));
+    assert_eq!(generate(&input, config(Hand_Edit_Policy::KEEP), |_: &str| -> Fmt_Result {unreachable!()}).unwrap_display(), None);
+    assert_eq!(generate(&input, config(Hand_Edit_Policy::CONFLICT), gen).unwrap_display(), Some(format!("x\n  << codegen foo >>\n  <<<<<<< hand-edited\n  edited\n  =======\n  new\n  >>>>>>> generated\n  << /codegen {} >>\ny\n", checksum("new\n"))));
+
+    // Regenerating an unresolved conflict refreshes only the generated code
+    let conflict = generate(&input, config(Hand_Edit_Policy::CONFLICT), gen).unwrap_display().unwrap();
+    assert_eq!(generate(&conflict, config(Hand_Edit_Policy::CONFLICT), gen).unwrap_display(), Some(conflict.clone()));
+    assert_eq!(generate(&conflict, config(Hand_Edit_Policy::CONFLICT), |_: &str| Some("newer".to_owned())).unwrap_display(), Some(format!("x\n  << codegen foo >>\n  <<<<<<< hand-edited\n  edited\n  =======\n  newer\n  >>>>>>> generated\n  << /codegen {} >>\ny\n", checksum("newer\n"))));
+    assert_eq!(generate(&conflict, config(Hand_Edit_Policy::CONFLICT), |_: &str| Some("edited".to_owned())).unwrap_display(), Some(format!("x\n  << codegen foo >>\n  edited\n  << /codegen {} >>\ny\n", checksum("edited\n"))));
+
+    // The user region would be written twice
+    let with_region = |_: &str| Some("a\n<< keep body >>\n<< /keep >>\nb".to_owned());
+    let generated = generate("<< codegen foo >>\n<< /codegen >>\n", config(Hand_Edit_Policy::ERROR), with_region).unwrap_display().unwrap();
+    let edited = generated.replace("a\n", "x\n");
+    let user_regions = |policy| Config{user_regions: true, ..config(policy)};
+    assert!(matches!(generate(&edited, user_regions(Hand_Edit_Policy::CONFLICT), with_region), Err(Gen_Error::CONFLICT_WITH_MARKERS{identifier, ..}) if identifier == "foo"));
+    assert!(generate(&edited, user_regions(Hand_Edit_Policy::OVERWRITE), with_region).is_ok());
+    // Without user regions, the markers are ordinary code
+    assert!(generate(&edited, config(Hand_Edit_Policy::CONFLICT), with_region).unwrap_display().unwrap().contains("<<<<<<< hand-edited\nx\n<< keep body >>\n"));
+    // So would the nested section
+    let nested = Config{nesting: Nesting::OUTER_FIRST, expand_generated_sections: true, ..config(Hand_Edit_Policy::CONFLICT)};
+    let with_nested = |identifier: &str| Some(match identifier {"foo" => "a\n<< codegen bar >>\n<< /codegen >>", _ => "b"}.to_owned());
+    let generated = generate("<< codegen foo >>\n<< /codegen >>\n", nested, with_nested).unwrap_display().unwrap();
+    assert!(matches!(generate(&generated.replace("a\n", "x\n"), nested, with_nested), Err(Gen_Error::CONFLICT_WITH_MARKERS{identifier, ..}) if identifier == "foo"));
+
+    let stale = check(&input, config(Hand_Edit_Policy::OVERWRITE), gen).unwrap_display();
+    assert_eq!(stale, vec![Stale_Section{identifier: "foo".to_owned(), lines: 2..=4, reason: Stale_Reason::CHECKSUM_MISMATCH}]);
+    assert_eq!(diff(&input, config(Hand_Edit_Policy::OVERWRITE), gen).unwrap_display().unwrap(), format!("--- input\n+++ input\n@@ -3,2 +3,2 @@ foo (hand-edited)\n-  edited\n-  << /codegen {} >>\n+  new\n+  << /codegen {} >>\n", checksum("old\n"), checksum("new\n")));
+    // A kept hand edit is up to date
+    assert_eq!(check(&input, config(Hand_Edit_Policy::KEEP), |_: &str| -> Fmt_Result {unreachable!()}).unwrap_display(), vec![]);
+    assert_eq!(diff(&input, config(Hand_Edit_Policy::KEEP), |_: &str| -> Fmt_Result {unreachable!()}).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_checksum_algorithm()
+  {
+    const SHA256 : Config = Config{checksum_bytes_to_store: 2, checksum_algorithm: Checksum_Algorithm::SHA256, .. CFG};
+    const CRC32 : Config = Config{checksum_bytes_to_store: 4, checksum_algorithm: Checksum_Algorithm::CRC32, .. CFG};
+    const BLAKE3 : Config = Config{checksum_bytes_to_store: 2, .. CFG};
+    let checksum = |algorithm: Checksum_Algorithm, len: usize| algorithm.checksum(b"42\n").to_string()[..2*len].to_owned();
+    let sha256 = format!("<< codegen foo >>\n42\n<< /codegen sha256:{} >>\n", checksum(Checksum_Algorithm::SHA256, 2));
+    let crc32 = format!("<< codegen foo >>\n42\n<< /codegen crc32:{} >>\n", checksum(Checksum_Algorithm::CRC32, 4));
+    let blake3 = format!("<< codegen foo >>\n42\n<< /codegen {} >>\n", checksum(Checksum_Algorithm::BLAKE3, 2));
+    let tagged_blake3 = format!("<< codegen foo >>\n42\n<< /codegen blake3:{} >>\n", checksum(Checksum_Algorithm::BLAKE3, 2));
+
+    assert_eq!(generate("<< codegen foo >>\n<< /codegen >>\n", SHA256, |_: &str| Some("42".to_owned())).unwrap_display(), Some(sha256.clone()));
+    assert_eq!(generate(&sha256, SHA256, |_: &str| None).unwrap_display(), None);
+    assert_eq!(generate(&tagged_blake3, BLAKE3, |_: &str| None).unwrap_display(), None);
+
+    assert_eq!(migrate_checksums(&sha256, CRC32).unwrap_display(), Some(crc32.clone()));
+    assert_eq!(migrate_checksums(&crc32, BLAKE3).unwrap_display(), Some(blake3.clone()));
+    assert_eq!(migrate_checksums(&tagged_blake3, SHA256).unwrap_display(), Some(sha256.clone()));
+    assert_eq!(check(&sha256, BLAKE3, |_: &str| None).unwrap_display(), vec![Stale_Section{identifier: "foo".to_owned(), lines: 1..=3, reason: Stale_Reason::CHECKSUM_ALGORITHM_CHANGED}]);
+
+    let edited = sha256.replace("42", "43");
+    assert!(matches!(migrate_checksums(&edited, BLAKE3), Err(Gen_Error::WRONG_CHECKSUM{actual, ..}) if actual.algorithm() == Checksum_Algorithm::SHA256));
+
+    // Longer checksums than the algorithm computes are clamped
+    assert_eq!(migrate_checksums(&crc32, Config{checksum_bytes_to_store: 8, ..CRC32}).unwrap_display(), None);
+    let blake3_full = format!("<< codegen foo >>\n42\n<< /codegen {} >>\n", checksum(Checksum_Algorithm::BLAKE3, 32));
+    assert_eq!(migrate_checksums(&blake3, Config{checksum_bytes_to_store: 40, ..BLAKE3}).unwrap_display(), Some(blake3_full.clone()));
+    assert_eq!(generate(&blake3_full, Config{checksum_bytes_to_store: 40, ..BLAKE3}, |_: &str| None).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_checksum_key()
+  {
+    let key = Checksum_Key::derive(b"secret");
+    let mac = Config{checksum_bytes_to_store: 16, checksum_algorithm: Checksum_Algorithm::BLAKE3_MAC, checksum_key: Some(&key), .. CFG};
+    let generated = generate("<< codegen foo >>\n<< /codegen >>\n", mac, |_: &str| Some("42".to_owned())).unwrap_display().unwrap();
+    let mac_42 = Checksum_Algorithm::BLAKE3_MAC.checksum_with_key(b"42\n", Some(&key)).unwrap().to_string();
+    assert_eq!(generated, format!("<< codegen foo >>\n42\n<< /codegen mac:{} >>\n", &mac_42[..32]));
+    assert_eq!(generate(&generated, mac, |_: &str| None).unwrap_display(), None);
+
+    // Recomputing the checksum without the key doesn't help
+    let forged = format!("<< codegen foo >>\n43\n<< /codegen mac:{} >>\n", &blake3::hash(b"43\n").to_hex()[..32]);
+    let error = generate(&forged, mac, |_: &str| None).unwrap_err();
+    assert_eq!(error, Gen_Error::INVALID_MAC{location: Location::new(None, 1, 1), identifier: "foo".to_owned()});
+    assert_eq!(error.to_string(), "line 1: section `foo` has an invalid MAC (edited without the checksum key)");
+    assert_eq!(check(&forged, mac, |_: &str| None).unwrap_display(), vec![Stale_Section{identifier: "foo".to_owned(), lines: 1..=3, reason: Stale_Reason::INVALID_MAC}]);
+
+    // Neither does removing the MAC, replacing it by a plain checksum or shortening it
+    let mac_43 = Checksum_Algorithm::BLAKE3_MAC.checksum_with_key(b"43\n", Some(&key)).unwrap().to_string();
+    let plain = format!("<< codegen foo >>\n43\n<< /codegen {} >>\n", blake3::hash(b"43\n").to_hex());
+    for downgraded in ["<< codegen foo >>\n43\n<< /codegen >>\n", &plain, &format!("<< codegen foo >>\n43\n<< /codegen mac:{} >>\n", &mac_43[..8])]
+    {
+      assert!(matches!(generate(downgraded, mac, |_: &str| None), Err(Gen_Error::INVALID_MAC{..})));
+      assert_eq!(check(downgraded, mac, |_: &str| None).unwrap_display(), vec![Stale_Section{identifier: "foo".to_owned(), lines: 1..=3, reason: Stale_Reason::INVALID_MAC}]);
+    }
+    // New sections and explicit migrations have no MAC yet
+    assert_eq!(generate("<< codegen foo >>\n<< /codegen >>\n", mac, |_: &str| Some("42".to_owned())).unwrap_display(), Some(generated.clone()));
+    assert_eq!(migrate_checksums(&generate("<< codegen foo >>\n<< /codegen >>\n", CFG, |_: &str| Some("42".to_owned())).unwrap_display().unwrap(), mac).unwrap_display(), Some(generated.clone()));
+    let short = Config{checksum_bytes_to_store: 4, .. mac};
+    assert_eq!(generate(&generated, short, |_: &str| None).unwrap_display(), None);
+
+    let guess = Checksum_Key::derive(b"guess");
+    let wrong_key = Config{checksum_key: Some(&guess), .. mac};
+    assert_ne!(wrong_key, mac);
+    assert_eq!(Config{checksum_key: Some(&Checksum_Key::derive(b"secret")), .. mac}, mac);
+    assert!(matches!(generate(&generated, wrong_key, |_: &str| None), Err(Gen_Error::INVALID_MAC{..})));
+
+    let no_key = Config{checksum_key: None, .. CFG};
+    assert_eq!(generate(&generated, no_key, |_: &str| None), Err(Gen_Error::MISSING_CHECKSUM_KEY{location: Location::new(None, 1, 1), identifier: "foo".to_owned()}));
+  }
+
+  #[test]
+  fn test_checksum_covers_identifier()
+  {
+    const COVERED : Config = Config{checksum_bytes_to_store: 4, checksum_covers_identifier: true, .. CFG};
+    const UNCOVERED : Config = Config{checksum_bytes_to_store: 4, .. CFG};
+    let input = "<< codegen foo(x) >>\n<< /codegen >>\n<< codegen bar >>\n<< /codegen >>\n";
+    let generated = generate(input, COVERED, |i: &str| Some(i.to_owned())).unwrap_display().unwrap();
+    assert_eq!(generate(&generated, COVERED, |_: &str| None).unwrap_display(), None);
+
+    let renamed = generated.replace("codegen bar", "codegen baz");
+    assert!(matches!(generate(&renamed, COVERED, |_: &str| None), Err(Gen_Error::WRONG_CHECKSUM{identifier, ..}) if identifier == "baz"));
+    let changed_argument = generated.replace("foo(x)", "foo(y)");
+    assert!(matches!(generate(&changed_argument, COVERED, |_: &str| None), Err(Gen_Error::WRONG_CHECKSUM{identifier, ..}) if identifier == "foo"));
+    let reformatted_arguments = generated.replace("foo(x)", "foo x");
+    assert_eq!(check(&reformatted_arguments, COVERED, |_: &str| None).unwrap_display(), vec![]);
+
+    // Only `migrate_checksums` accepts checksums of the other mode, otherwise
+    // a renamed section could pass as written in the other mode
+    let migrated = migrate_checksums(&generated, UNCOVERED).unwrap_display().unwrap();
+    assert_eq!(check(&generated, UNCOVERED, |_: &str| None).unwrap_display(), vec![
+      Stale_Section{identifier: "foo".to_owned(), lines: 1..=3, reason: Stale_Reason::CHECKSUM_MISMATCH},
+      Stale_Section{identifier: "bar".to_owned(), lines: 4..=6, reason: Stale_Reason::CHECKSUM_MISMATCH},
+    ]);
+    assert!(matches!(generate(&migrated, COVERED, |_: &str| None), Err(Gen_Error::WRONG_CHECKSUM{identifier, ..}) if identifier == "foo"));
+    assert_eq!(generate(&migrated.replace("codegen bar", "codegen baz"), UNCOVERED, |_: &str| None).unwrap_display(), None);
+    assert_eq!(migrate_checksums(&migrated, COVERED).unwrap_display(), Some(generated));
+  }
+
+  #[test]
+  fn test_preserve_marker_lines()
+  {
+    const PRESERVE : Config = Config{preserve_marker_lines: true, .. CFG};
+    let gen = |_: &str| Some("42".to_owned());
+    let input = "  /*<<codegen   foo>>*/\n  x\n    <<  /codegen >>   \n";
+    assert_eq!(generate(input, CFG, gen).unwrap_display(), Some("  /*<< codegen foo >>*/\n  42\n  << /codegen >>   \n".to_owned()));
+    assert_eq!(generate(input, PRESERVE, gen).unwrap_display(), Some("  /*<<codegen   foo>>*/\n  42\n    <<  /codegen >>   \n".to_owned()));
+    assert_eq!(generate(input, Config{checksum_bytes_to_store: 2, ..PRESERVE}, gen).unwrap_display(), Some("  /*<<codegen   foo>>*/\n  42\n    <<  /codegen a160 >>   \n".to_owned()));
+
+    let input = "<<codegen foo(a,  b)>>\n42\n<</codegen   a160>>\n";
+    assert_eq!(generate(input, Config{checksum_bytes_to_store: 2, ..PRESERVE}, gen).unwrap_display(), None);
+    assert_eq!(generate(input, PRESERVE, gen).unwrap_display(), Some("<<codegen foo(a,  b)>>\n42\n<</codegen>>\n".to_owned()));
+    assert_eq!(generate(input, Config{checksum_bytes_to_store: 2, checksum_algorithm: Checksum_Algorithm::CRC32, ..PRESERVE}, gen).unwrap_display(), Some("<<codegen foo(a,  b)>>\n42\n<</codegen   crc32:d186>>\n".to_owned()));
+  }
+
+  #[test]
+  fn test_generate_with_report()
+  {
+    let input = "<< codegen a >>\n1\n<< /codegen >>\nx\n<< codegen b >>\n2\n<< /codegen >>\n<< codegen c >>\n42\n<< /codegen a160 >>\n<< codegen d >>\n<< /codegen >>\n";
+    let gen = |ctx: &Section_Context| match ctx.identifier
+    {
+      "a" => Some("1".to_owned()),
+      "b" => Some("2\n2".to_owned()),
+      _ => None,
+    };
+    let (output, report) = generate_with_report(input, CFG, gen).unwrap_display();
+    assert_eq!(output, Some("<< codegen a >>\n1\n<< /codegen >>\nx\n<< codegen b >>\n2\n2\n<< /codegen >>\n<< codegen c >>\n42\n<< /codegen >>\n<< codegen d >>\n<< /codegen >>\n".to_owned()));
+
+    let section = |identifier: &str, old_lines, new_lines, status, old_checksum: &str| Section_Report{identifier: identifier.to_owned(), old_lines, new_lines, status, old_checksum: old_checksum.to_owned(), new_checksum: String::new()};
+    assert_eq!(report.sections, vec![
+      section("a", 1..=3, 1..=3, Section_Status::UNCHANGED, ""),
+      section("b", 5..=7, 5..=8, Section_Status::REGENERATED, ""),
+      section("c", 8..=10, 9..=11, Section_Status::CHECKSUM_UPDATED, "a160"),
+      section("d", 11..=12, 12..=13, Section_Status::KEPT, ""),
+    ]);
+    assert_eq!(report.changed().map(|section| section.identifier.as_str()).collect::<Vec<_>>(), vec!["b", "c"]);
+    assert_eq!(report.sections[1].to_string(), "lines 5-7: section `b` was regenerated");
+
+    let edited = "<< codegen a >>\n2\n<< /codegen a160 >>\n";
+    let (output, report) = generate_with_report(edited, Config{checksum_bytes_to_store: 2, on_hand_edit: Hand_Edit_Policy::OVERWRITE, ..CFG}, gen).unwrap_display();
+    assert_eq!(output, Some("<< codegen a >>\n1\n<< /codegen 50cc >>\n".to_owned()));
+    assert_eq!(report.sections[0].status, Section_Status::OVERWRITTEN);
+    assert_eq!(report.sections[0].to_string(), "lines 1-3: section `a` had its hand edit overwritten");
+  }
+
+  #[test]
+  fn test_strict()
+  {
+    const STRICT : Config = Config{strict: true, .. CFG};
+    let input = "<< codegen foo >>\n<< /codegen >>\n  << codegen fooo >>\n  << /codegen >>\n<< codegen bar >>\nx\n<< /codegen >>\n<< codegen baz >>\n<< /codegen >>\n";
+    let gen = |ctx: &Section_Context| match ctx.identifier
+    {
+      "foo" => Generator_Output::CODE("42".to_owned()),
+      "bar" => Generator_Output::KEEP,
+      _ => Generator_Output::NOT_MINE,
+    };
+    let expected = "<< codegen foo >>\n42\n<< /codegen >>\n  << codegen fooo >>\n  << /codegen >>\n<< codegen bar >>\nx\n<< /codegen >>\n<< codegen baz >>\n<< /codegen >>\n";
+    assert_eq!(generate(input, CFG, gen).unwrap_display(), Some(expected.to_owned()));
+
+    let unclaimed = |line, column, identifier: &str| Unclaimed_Section{location: Location::new(None, line, column), identifier: identifier.to_owned()};
+    let error = generate(input, STRICT, gen).unwrap_err();
+    assert_eq!(error, Gen_Error::UNCLAIMED_SECTIONS(vec![unclaimed(3, 3, "fooo"), unclaimed(8, 1, "baz")]));
+    assert_eq!(error.to_string(), "no generator claimed the sections `fooo` (line 3), `baz` (line 8)");
+
+    // Any kind of generator may return a `Generator_Output`
+    assert!(matches!(check(input, STRICT, gen), Err(Gen_Error::UNCLAIMED_SECTIONS(_))));
+    assert_eq!(generate(input, STRICT, |_: &str| Generator_Output::KEEP).unwrap_display(), None);
+
+    // `None` means "not mine"
+    assert!(generate(input, STRICT, |_: &str| None).is_err());
+    assert_eq!(migrate_checksums(input, STRICT).unwrap_display(), None);
+  }
+
+  #[test]
+  fn test_nesting()
+  {
+    let input = "<< codegen outer >>\nimpl Foo\n{\n  << codegen body >>\n  old\n  << /codegen >>\n}\n<< /codegen >>\n";
+    let expected = "<< codegen outer >>\nimpl Foo\n{\n  << codegen body >>\n  fn foo() {}\n  << /codegen >>\n}\n<< /codegen >>\n";
+    let run = |input: &str, nesting| {
+      let mut calls = vec![];
+      let output = generate(input, Config{nesting, expand_generated_sections: true, ..CFG}, |ctx: &Section_Context| {
+        calls.push(format!("{}({:?}) in {:?}", ctx.identifier, ctx.old_code, ctx.parent));
+        match ctx.identifier
+        {
+          // Leaves the body empty, so the nested section keeps its code
+          "outer" => Some("impl Foo\n{\n  << codegen body >>\n  << /codegen >>\n}\n".to_owned()),
+          _ => Some("fn foo() {}".to_owned()),
+        }
+      }).unwrap_display();
+      (output, calls)
+    };
+
+    assert_eq!(run(input, Nesting::OUTER_FIRST), (Some(expected.to_owned()), vec![
+      "outer(\"impl Foo\\n{\\n  << codegen body >>\\n  old\\n  << /codegen >>\\n}\\n\") in None".to_owned(),
+      "body(\"old\\n\") in Some(\"outer\")".to_owned(),
+    ]));
+    assert_eq!(run(input, Nesting::DEPTH_FIRST), (Some(expected.to_owned()), vec![
+      "body(\"old\\n\") in Some(\"outer\")".to_owned(),
+      "outer(\"impl Foo\\n{\\n  << codegen body >>\\n  fn foo() {}\\n  << /codegen >>\\n}\\n\") in None".to_owned(),
+    ]));
+    assert_eq!(run(expected, Nesting::OUTER_FIRST).0, None);
+    assert_eq!(run(expected, Nesting::DEPTH_FIRST).0, None);
+
+    assert!(matches!(generate(input, CFG, |_: &str| None), Err(Gen_Error::FIND(parse_file::Parse_Error::NESTED_BEGIN_MARKER{..}))));
+  }
+
+  #[test]
+  fn test_nesting_without_expansion()
+  {
+    let input = "<< codegen outer >>
+<< codegen body >>
+old
+<< /codegen >>
+<< codegen outer >>
+<< /codegen >>
+<< /codegen >>
+";
+    let gen = |outer: Option<&'static str>| move |identifier: &str| match identifier
+    {
+      "outer" => outer.map(str::to_owned),
+      _ => Some("new".to_owned()),
+    };
+    for nesting in [Nesting::OUTER_FIRST, Nesting::DEPTH_FIRST]
+    {
+      let cfg = Config{nesting, ..CFG};
+
+      // The nested sections of the input are generated, if the outer section keeps its code
+      let expected = "<< codegen outer >>\n<< codegen body >>\nnew\n<< /codegen >>\n<< codegen outer >>\n<< /codegen >>\n<< /codegen >>\n";
+      assert_eq!(generate(input, cfg, gen(None)).unwrap_display(), Some(expected.to_owned()), "{nesting:?}");
+      // A section nested in a section with the same identifier is no expansion cycle
+      assert_eq!(generate(input, Config{expand_generated_sections: true, ..cfg}, gen(None)).unwrap_display(), Some(expected.to_owned()), "{nesting:?}");
+
+      // Generated code replaces the nested sections and its markers are not expanded
+      assert_eq!(generate(input, cfg, gen(Some("x"))).unwrap_display(), Some("<< codegen outer >>\nx\n<< /codegen >>\n".to_owned()));
+      let with_markers = gen(Some("<< codegen body >>\n<< /codegen >>"));
+      assert!(matches!(generate(input, cfg, with_markers), Err(Gen_Error::FORBIDDEN{identifier, ..}) if identifier == "outer"));
+      let escaped = generate(input, Config{escape_markers: true, ..cfg}, with_markers).unwrap_display().unwrap();
+      assert_eq!(escaped, format!("<< codegen outer >>\n{}<< /codegen >>\n", parse_file::escape_markers("<< codegen body >>\n<< /codegen >>\n", &Marker_Style::DEFAULT)));
+      assert_eq!(generate("<< codegen outer >>\n<< /codegen >>\n", Config{expand_generated_sections: true, ..cfg}, with_markers).unwrap_display(), Some("<< codegen outer >>\n<< codegen body >>\nnew\n<< /codegen >>\n<< /codegen >>\n".to_owned()));
+    }
+  }
+
+  #[test]
+  fn test_nesting_index()
+  {
+    let input = "<< codegen a >>\n<< /codegen >>\n<< codegen outer >>\n<< codegen b >>\n<< /codegen >>\n<< codegen c >>\n<< /codegen >>\n<< /codegen >>\n";
+    let mut indices = vec![];
+    generate(input, Config{nesting: Nesting::OUTER_FIRST, ..CFG}, |ctx: &Section_Context| {indices.push((ctx.identifier.to_owned(), ctx.index)); None}).unwrap_display();
+    assert_eq!(indices, [("a".to_owned(), 0), ("outer".to_owned(), 1), ("b".to_owned(), 0), ("c".to_owned(), 1)]);
+  }
+
+  #[test]
+  fn test_nesting_carried_code()
+  {
+    const NESTED : Config = Config{nesting: Nesting::OUTER_FIRST, expand_generated_sections: true, .. CFG};
+    let gen = |identifier: &str| match identifier
+    {
+      "outer" => Some("{\n  << codegen body >>\n  << /codegen >>\n}\nnew".to_owned()),
+      _ => None,
+    };
+
+    // The carried code keeps the line endings of the file
+    let input = "<< codegen outer >>\r\n{\r\n  << codegen body >>\r\n  old\r\n  << /codegen >>\r\n}\r\n<< /codegen >>\r\n";
+    let output = generate(input, NESTED, gen).unwrap_display();
+    assert_eq!(output, Some("<< codegen outer >>\r\n{\r\n  << codegen body >>\r\n  old\r\n  << /codegen >>\r\n}\r\nnew\r\n<< /codegen >>\r\n".to_owned()));
+    assert_eq!(generate(&output.unwrap(), NESTED, gen).unwrap_display(), None);
+
+    let input = "<< codegen outer >>\n{\n  << codegen body >>\nold\n  << /codegen >>\n}\n<< /codegen >>\n";
+    let error = generate(input, NESTED, gen).unwrap_err();
+    assert_eq!(error, Gen_Error::UNINDENT_ERROR{location: Location::new(None, 4, 1), identifier: "body".to_owned(), error: crate::indentation::Unindent_Error::NON_WS_IN_INDENTATION{line: 1, column: 1}});
+  }
+
+  #[test]
+  fn test_nesting_arguments()
+  {
+    let input = "<< codegen fields >>\n<< codegen field(a) >>\nold a\n<< /codegen >>\n<< /codegen >>\n";
+    for nesting in [Nesting::OUTER_FIRST, Nesting::DEPTH_FIRST]
+    {
+      let mut calls = vec![];
+      let output = generate(input, Config{nesting, expand_generated_sections: true, ..CFG}, |ctx: &Section_Context| {
+        calls.push(format!("{}{}", ctx.identifier, ctx.arguments.source));
+        match ctx.identifier
+        {
+          // The new `field(b)` neither gets the code of `field(a)` nor is kept
+          "fields" => Some("<< codegen field(a) >>\n<< /codegen >>\n<< codegen field(b) >>\n<< /codegen >>\n".to_owned()),
+          _ => Some(format!("new {}", ctx.arguments.source)),
+        }
+      }).unwrap_display();
+      assert_eq!(output, Some("<< codegen fields >>\n<< codegen field(a) >>\nnew (a)\n<< /codegen >>\n<< codegen field(b) >>\nnew (b)\n<< /codegen >>\n<< /codegen >>\n".to_owned()));
+      assert_eq!(calls.len(), 3);
+      assert!(calls.contains(&"field(b)".to_owned()));
+    }
+  }
+
+  #[test]
+  fn test_expansion_limits()
+  {
+    const NESTED : Config = Config{nesting: Nesting::OUTER_FIRST, expand_generated_sections: true, .. CFG};
+    let section = |name: &str| format!("<< codegen {name} >>\n<< /codegen >>\n");
+
+    // Sections introduced by generated code are expanded in the same run
+    let gen = |ctx: &Section_Context| match (ctx.identifier, ctx.arguments.list.first().map(|argument| argument.value))
+    {
+      ("tree", Some("0")) => Some("leaf".to_owned()),
+      ("tree", Some(n)) => Some(section(&format!("tree({})", n.parse::<u8>().unwrap()-1))),
+      _ => None,
+    };
+    let output = generate(&section("tree(2)"), NESTED, gen).unwrap_display().unwrap();
+    assert_eq!(output, "<< codegen tree(2) >>\n<< codegen tree(1) >>\n<< codegen tree(0) >>\nleaf\n<< /codegen >>\n<< /codegen >>\n<< /codegen >>\n");
+    assert_eq!(generate(&output, NESTED, gen).unwrap_display(), None);
+
+    let error = generate(&section("tree(2)"), Config{max_nesting_depth: 1, ..NESTED}, gen).unwrap_err();
+    assert_eq!(error, Gen_Error::NESTING_TOO_DEEP{location: Location::new(None, 3, 1), identifier: "tree".to_owned(), max_depth: 1});
+
+    let gen = |identifier: &str| match identifier
+    {
+      "a" => Some(section("b")),
+      "b" => Some(section("a")),
+      _ => None,
+    };
+    let error = generate(&section("a"), NESTED, gen).unwrap_err();
+    assert_eq!(error, Gen_Error::EXPANSION_CYCLE{location: Location::new(None, 3, 1), identifier: "a".to_owned(), cycle: vec!["a".to_owned(), "b".to_owned(), "a".to_owned()]});
+    assert_eq!(error.to_string(), "line 3: section `a` expands into itself: a -> b -> a");
+  }
+
+  #[test]
+  fn test_expansion_cycle_in_nested_input()
+  {
+    const NESTED : Config = Config{nesting: Nesting::OUTER_FIRST, expand_generated_sections: true, .. CFG};
+    let input = "<< codegen outer >>\n<< codegen outer >>\n<< /codegen >>\n<< /codegen >>\n";
+
+    // The nested `outer` of the input is generated like any other section
+    let output = generate(input, NESTED, |identifier: &str| match identifier
+    {
+      "outer" => Some("x".to_owned()),
+      _ => None,
+    }).unwrap_display();
+    assert_eq!(output, Some("<< codegen outer >>\nx\n<< /codegen >>\n".to_owned()));
+
+    // Only a generator returning its own section again is a cycle
+    let error = generate(input, NESTED, |identifier: &str| match identifier
+    {
+      "outer" => Some("<< codegen outer >>\n<< /codegen >>\n".to_owned()),
+      _ => None,
+    }).unwrap_err();
+    assert_eq!(error, Gen_Error::EXPANSION_CYCLE{location: Location::new(None, 2, 1), identifier: "outer".to_owned(), cycle: vec!["outer".to_owned(), "outer".to_owned()]});
+  }
+
+  #[test]
+  fn test_nesting_checksums()
+  {
+    const NESTED : Config = Config{checksum_bytes_to_store: 4, nesting: Nesting::OUTER_FIRST, .. CFG};
+    let input = "<< codegen outer >>\n<< codegen inner >>\n<< /codegen >>\n<< /codegen >>\n";
+    let gen = |version: &'static str| move |identifier: &str| match identifier
+    {
+      "inner" => Some(format!("{version}\n")),
+      _ => None,
+    };
+
+    let v1 = generate(input, NESTED, gen("v1")).unwrap_display().unwrap();
+    assert_eq!(generate(&v1, NESTED, gen("v1")).unwrap_display(), None);
+
+    // Regenerating the nested section updates the checksum of the outer one
+    let v2 = generate(&v1, NESTED, gen("v2")).unwrap_display().unwrap();
+    assert!(v2.contains("v2\n"));
+    assert_eq!(generate(&v2, NESTED, gen("v2")).unwrap_display(), None);
+
+    // Hand edits of nested sections are detected and reported against the nested section
+    let edited = v2.replace("v2", "edited");
+    assert!(matches!(generate(&edited, NESTED, gen("v2")), Err(Gen_Error::WRONG_CHECKSUM{location, identifier, ..}) if identifier == "inner" && location == Location::new(None, 2, 1)));
+    assert_eq!(check(&edited, NESTED, gen("v2")).unwrap_display(), vec![Stale_Section{identifier: "inner".to_owned(), lines: 2..=4, reason: Stale_Reason::CHECKSUM_MISMATCH}]);
+    let edited = v2.replace("<< /codegen", "x\n<< /codegen").replacen("x\n", "", 1);
+    assert!(matches!(generate(&edited, NESTED, gen("v2")), Err(Gen_Error::WRONG_CHECKSUM{identifier, ..}) if identifier == "outer"));
+  }
+
+  #[test]
+  fn test_nesting_report()
+  {
+    let input = "<< codegen outer >>\n{\n  << codegen a >>\n  << /codegen >>\n  << codegen b >>\n  old\n  << /codegen >>\n}\n<< /codegen >>\n";
+    let gen = |ctx: &Section_Context| match ctx.identifier
+    {
+      "outer" => None,
+      "a" => Some("1\n2".to_owned()),
+      _ => Some("old".to_owned()),
+    };
+    let section = |identifier: &str, old_lines, new_lines, status| Section_Report{identifier: identifier.to_owned(), old_lines, new_lines, status, old_checksum: String::new(), new_checksum: String::new()};
+    for nesting in [Nesting::OUTER_FIRST, Nesting::DEPTH_FIRST]
+    {
+      let (_, report) = generate_with_report(input, Config{nesting, ..CFG}, gen).unwrap_display();
+      assert_eq!(report.sections, vec![
+        section("outer", 1..=9, 1..=11, Section_Status::REGENERATED),
+        section("a", 3..=4, 3..=6, Section_Status::REGENERATED),
+        section("b", 5..=7, 7..=9, Section_Status::UNCHANGED),
+      ], "{nesting:?}");
+    }
+  }
+
+  #[test]
+  fn test_user_regions()
+  {
+    const CHECKSUM : Config = Config{checksum_bytes_to_store: 4, user_regions: true, .. CFG};
+    let gen = |version: &'static str| move |_: &str| Some(format!("fn {version}()\n{{\n  // << keep body >>\n  // << /keep >>\n}}\n"));
+
+    let v1 = generate("<< codegen f >>\n<< /codegen >>\n", CHECKSUM, gen("v1")).unwrap_display().unwrap();
+    assert_eq!(v1, "<< codegen f >>\nfn v1()\n{\n  // << keep body >>\n  // << /keep >>\n}\n<< /codegen d32eb843 >>\n");
+
+    // The content of user regions is neither a hand edit nor overwritten
+    let edited = v1.replace("  // << /keep >>\n", "  return 42;\n  // << /keep >>\n");
+    assert_eq!(generate(&edited, CHECKSUM, gen("v1")).unwrap_display(), None);
+    assert_eq!(generate(&edited, CHECKSUM, gen("v2")).unwrap_display(), Some(edited.replace("v1", "v2").replace("d32eb843", "10c87a3d")));
+
+    // The content follows the marker to a different nesting depth
+    let wrapped = |_: &str| Some("fn v3()\n{\n  if true\n  {\n    // << keep body >>\n    // << /keep >>\n  }\n}\n".to_owned());
+    let v3 = generate(&edited, CHECKSUM, wrapped).unwrap_display().unwrap();
+    assert!(v3.contains("  {\n    // << keep body >>\n    return 42;\n    // << /keep >>\n  }\n"));
+    assert_eq!(generate(&v3, CHECKSUM, wrapped).unwrap_display(), None);
+    assert_eq!(generate(&v3, CHECKSUM, gen("v1")).unwrap_display(), Some(edited.clone()));
+
+    // Edits outside of user regions are still detected
+    assert!(matches!(generate(&edited.replace("}\n<<", "} \n<<"), CHECKSUM, gen("v1")), Err(Gen_Error::WRONG_CHECKSUM{..})));
+
+    let error = generate(&edited, CHECKSUM, |_: &str| Some("fn v2() {}\n".to_owned())).unwrap_err();
+    assert_eq!(error.to_string(), "line 4: section `f` has user region `body`, which is missing in the generated code");
+    let error = generate(&edited, CHECKSUM, |_: &str| Some("// << keep body >>\n".to_owned())).unwrap_err();
+    assert_eq!(error.to_string(), "line 1: the generated code for section `f` has user region `body` without an end marker (line 1 of the generated code)");
+    let error = generate("<< codegen f >>\n<< /keep >>\n<< /codegen >>\n", CHECKSUM, gen("v1")).unwrap_err();
+    assert_eq!(error.to_string(), "line 2: section `f` has a user region end marker without a begin marker");
+
+    // Without `Config::user_regions`, editing a user region is a hand edit and the markers may be dropped
+    const NO_USER_REGIONS : Config = Config{user_regions: false, .. CHECKSUM};
+    assert_eq!(generate(&v1, NO_USER_REGIONS, gen("v1")).unwrap_display(), None);
+    assert!(matches!(generate(&edited, NO_USER_REGIONS, gen("v1")), Err(Gen_Error::WRONG_CHECKSUM{..})));
+    assert!(generate(&v1, NO_USER_REGIONS, |_: &str| Some("fn v2() {}\n".to_owned())).is_ok());
+  }
+
+  #[test]
+  fn test_inline()
+  {
+    const CHECKSUM : Config = Config{checksum_bytes_to_store: 4, .. CFG};
+    let gen = |code: &'static str| move |_: &str| Some(code.to_owned());
+
+    let input = "x\n  int sizes[] = {/*<< codegen sizes >>*/0/*<< /codegen >>*/};\ny\n";
+    assert_eq!(generate(input, CFG, gen("1,2,3\n")).unwrap_display(), Some("x\n  int sizes[] = {/*<< codegen sizes >>*/1,2,3/*<< /codegen >>*/};\ny\n".to_owned()));
+    assert_eq!(generate(input, CFG, gen("0")).unwrap_display(), None);
+
+    let generated = generate(input, CHECKSUM, gen("42")).unwrap_display().unwrap();
+    assert_eq!(generated, "x\n  int sizes[] = {/*<< codegen sizes >>*/42/*<< /codegen da136474 >>*/};\ny\n");
+    assert_eq!(generate(&generated, CHECKSUM, gen("42")).unwrap_display(), None);
+    assert!(matches!(generate(&generated.replace("42", "43"), CHECKSUM, gen("42")), Err(Gen_Error::WRONG_CHECKSUM{..})));
+    assert!(matches!(generate(&generated.replace("42", "43"), Config{on_hand_edit: Hand_Edit_Policy::CONFLICT, ..CHECKSUM}, gen("42")), Err(Gen_Error::WRONG_CHECKSUM{..})));
+
+    let (generated, report) = generate_with_report("a\nx = /*<<codegen n>>*/0/*<</codegen>>*/;\nb\n", Config{preserve_marker_lines: true, ..CFG}, |ctx: &Section_Context| Some(format!("{}7", ctx.indentation))).unwrap_display();
+    assert_eq!(generated, Some("a\nx = /*<<codegen n>>*/7/*<</codegen>>*/;\nb\n".to_owned()));
+    assert_eq!(report.sections[0].old_lines, 2..=2);
+    assert_eq!(report.sections[0].new_lines, 2..=2);
+
+    let region = Config{marker_style: Marker_Style::REGION, ..CFG};
+    assert_eq!(generate("#region gen:n 0 #endregion gen\n", region, gen("7")).unwrap_display(), Some("#region gen:n 7 #endregion gen\n".to_owned()));
+    assert_eq!(generate("#region gen:n 0 #endregion gen\n", region, gen("0")).unwrap_display(), None);
+    // Only the separator the identifier needs is inserted
+    assert_eq!(generate("x = #region gen:n;#endregion gen;\n", region, gen("7")).unwrap_display(), Some("x = #region gen:n 7#endregion gen;\n".to_owned()));
+    assert_eq!(generate("x = #region gen:n;#endregion gen;\n", Config{preserve_marker_lines: true, ..region}, gen("7")).unwrap_display(), Some("x = #region gen:n 7#endregion gen;\n".to_owned()));
+    assert_eq!(generate("x = #region gen:n  0  #endregion gen;\n", Config{preserve_marker_lines: true, ..region}, gen("7")).unwrap_display(), Some("x = #region gen:n  7  #endregion gen;\n".to_owned()));
+    let generated = generate("#region gen:n 0 #endregion gen\n", Config{checksum_bytes_to_store: 2, ..region}, gen("7")).unwrap_display().unwrap();
+    assert_eq!(generate(&generated, Config{checksum_bytes_to_store: 2, ..region}, gen("7")).unwrap_display(), None);
+
+    let error = generate(input, CFG, gen("1,\n2")).unwrap_err();
+    assert_eq!(error.to_string(), "line 2: the generated code for the inline section `sizes` spans multiple lines");
+    assert!(matches!(generate(input, CFG, gen("<< codegen x >>")), Err(Gen_Error::FORBIDDEN{..})));
+  }
+
+  #[test]
+  fn test_named_end_markers()
+  {
+    const NAMED : Config = Config{named_end_markers: true, .. CFG};
+    let gen = |_: &str| Some("42".to_owned());
+
+    assert_eq!(generate("<< codegen foo >>\n<< /codegen >>\n", NAMED, gen).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen foo >>\n".to_owned()));
+    assert_eq!(generate("<< codegen foo >>\n42\n<< /codegen foo >>\n", NAMED, gen).unwrap_display(), None);
+    // Up to date sections keep their end markers
+    assert_eq!(generate("<< codegen foo >>\n42\n<< /codegen foo >>\n", CFG, gen).unwrap_display(), None);
+    assert_eq!(generate("<< codegen foo >>\n<< /codegen >>\n", Config{checksum_bytes_to_store: 2, ..NAMED}, gen).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen foo a160 >>\n".to_owned()));
+    // A single untagged token is the name, so a hex-looking name isn't taken for a checksum
+    const NAMED_CHECKSUM : Config = Config{checksum_bytes_to_store: 2, ..NAMED};
+    let unnamed = "<< codegen foo >>\n42\n<< /codegen a160 >>\n";
+    assert_eq!(generate(unnamed, NAMED_CHECKSUM, gen).unwrap_err().to_string(), "line 3: end marker named `a160` closes section `foo` (line 1)");
+    assert_eq!(generate(unnamed, Config{named_end_markers: false, ..NAMED_CHECKSUM}, gen).unwrap_display(), None);
+    assert_eq!(generate("<< codegen beef >>\n42\n<< /codegen beef a160 >>\n", NAMED_CHECKSUM, gen).unwrap_display(), None);
+    assert_eq!(generate("<< codegen foo >>\n42\n<< /codegen blake3:a160 >>\n", NAMED_CHECKSUM, gen).unwrap_display(), None);
+    let regenerated = generate("<< codegen foo >>\n42\n<< /codegen blake3:a160 >>\n", NAMED_CHECKSUM, |_: &str| Some("43".to_owned())).unwrap_display().unwrap();
+    assert_eq!(regenerated, "<< codegen foo >>\n43\n<< /codegen foo blake3:f06c >>\n");
+    assert_eq!(generate(&regenerated, NAMED_CHECKSUM, |_: &str| Some("43".to_owned())).unwrap_display(), None);
+    assert!(matches!(generate(&regenerated.replace("43", "44"), NAMED_CHECKSUM, gen), Err(Gen_Error::WRONG_CHECKSUM{..})));
+    // Unnamed end markers get their names by migrating or formatting them
+    assert_eq!(migrate_checksums("<< codegen foo >>\n42\n<< /codegen a1 >>\n", NAMED_CHECKSUM).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen foo a160 >>\n".to_owned()));
+    assert_eq!(format_markers(unnamed, NAMED_CHECKSUM).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen foo a160 >>\n".to_owned()));
+    let error = generate("<< codegen beef >>\n42\n<< /codegen bar >>\n", NAMED, gen).unwrap_err();
+    assert_eq!(error.to_string(), "line 3: end marker named `bar` closes section `beef` (line 1)");
+    assert!(matches!(generate("x = /*<< codegen beef >>*/42/*<< /codegen bar >>*/;\n", NAMED, gen), Err(Gen_Error::FIND(parse_file::Parse_Error::MISMATCHED_END_MARKER{..}))));
+    assert!(matches!(format_markers("<< codegen beef >>\n42\n<< /codegen bar >>\n", NAMED), Err(parse_file::Parse_Error::MISMATCHED_END_MARKER{..})));
+
+    // Names written by hand are kept
+    assert_eq!(generate("<< codegen foo >>\n0\n<< /codegen foo >>\n", CFG, gen).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen foo >>\n".to_owned()));
+    assert_eq!(generate("<< codegen beef >>\n<< /codegen beef >>\n", Config{checksum_bytes_to_store: 2, ..CFG}, gen).unwrap_display(), Some("<< codegen beef >>\n42\n<< /codegen beef a160 >>\n".to_owned()));
+    assert_eq!(generate("<< codegen beef >>\n<< /codegen beef >>\n", Config{checksum_bytes_to_store: 2, preserve_marker_lines: true, ..CFG}, gen).unwrap_display(), Some("<< codegen beef >>\n42\n<< /codegen beef a160 >>\n".to_owned()));
+    assert_eq!(generate("x = /*<< codegen foo >>*/0/*<< /codegen foo >>*/;\n", CFG, gen).unwrap_display(), Some("x = /*<< codegen foo >>*/42/*<< /codegen foo >>*/;\n".to_owned()));
+    assert_eq!(generate("x = /*<< codegen foo >>*/0/*<< /codegen >>*/;\n", NAMED, gen).unwrap_display(), Some("x = /*<< codegen foo >>*/42/*<< /codegen foo >>*/;\n".to_owned()));
+    assert_eq!(generate("#region gen:foo\n#endregion gen\n", Config{marker_style: Marker_Style::REGION, ..NAMED}, gen).unwrap_display(), Some("#region gen:foo\n42\n#endregion gen\n".to_owned()));
+
+    let error = generate("<< codegen foo >>\n<< /codegen bar >>\n", CFG, gen).unwrap_err();
+    assert_eq!(error.to_string(), "line 2: end marker named `bar` closes section `foo` (line 1)");
+  }
+
+  #[test]
+  fn test_final_linebreak()
+  {
+    let gen = |_: &str| Some("42".to_owned());
+    assert_eq!(generate("<< codegen x >>\n<< /codegen >>", CFG, gen).unwrap_display(), Some("<< codegen x >>\n42\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("<< codegen x >>\n<< /codegen >>\n", CFG, gen).unwrap_display(), Some("<< codegen x >>\n42\n<< /codegen >>\n".to_owned()));
+    assert_eq!(generate("<< codegen x >>\r\n<< /codegen >>", CFG, gen).unwrap_display(), Some("<< codegen x >>\r\n42\r\n<< /codegen >>".to_owned()));
+    assert_eq!(generate("<< codegen x >>\n<< /codegen >>\nx", CFG, gen).unwrap_display(), Some("<< codegen x >>\n42\n<< /codegen >>\nx".to_owned()));
+    assert_eq!(generate("<< codegen x >>\n42\n<< /codegen >>", CFG, gen).unwrap_display(), None);
+  }
+
   #[test]
   fn test_hash_and_indentation()
   {
@@ -227,8 +2020,8 @@ mod test
 
     const CKSM_4 : Config = Config{checksum_bytes_to_store: 2, .. CFG};
 
-    assert_eq!(generate("<< codegen x >>\n<< /codegen >>", CKSM_4, gen).unwrap_display(), Some("<< codegen x >>\n42\n  137\n1337\n<< /codegen 2d1c >>\n".to_owned()));
-    assert_eq!(generate("  << codegen x >>\n<< /codegen >>", CKSM_4, gen).unwrap_display(), Some("  << codegen x >>\n  42\n    137\n  1337\n  << /codegen 2d1c >>\n".to_owned()));
+    assert_eq!(generate("<< codegen x >>\n<< /codegen >>", CKSM_4, gen).unwrap_display(), Some("<< codegen x >>\n42\n  137\n1337\n<< /codegen 2d1c >>".to_owned()));
+    assert_eq!(generate("  << codegen x >>\n<< /codegen >>", CKSM_4, gen).unwrap_display(), Some("  << codegen x >>\n  42\n    137\n  1337\n  << /codegen 2d1c >>".to_owned()));
   }
   
   #[test]
@@ -244,5 +2037,6 @@ mod test
   }
 }
 
-use std::fmt;
-use fmt::Write;
\ No newline at end of file
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+use std::path::Path;
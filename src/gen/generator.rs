@@ -0,0 +1,129 @@
+/// Generates the code of the sections, usually a closure or a function.
+///
+/// Implemented for closures taking the identifier (`FnMut(&str)`), the
+/// identifier and the arguments (`FnMut(&str, &Arguments)`) or a
+/// [`Section_Context`] (`FnMut(&Section_Context)`), returning a
+/// [`Generator_Return`]. `Input` tells these apart and is inferred from the
+/// parameter types, so closures must annotate them, like `|name: &str| ...`.
+pub trait Generator<Input>
+{
+  /// The error the generator may fail with, see [`Gen_Error::GENERATOR`]
+  type Error;
+
+  fn generate(&mut self, ctx: &Section_Context) -> Result<Generator_Output, Self::Error>;
+}
+
+impl<F, R> Generator<fn(&str)> for F
+where F: FnMut(&str) -> R,
+      R: Generator_Return,
+{
+  type Error = R::Error;
+
+  fn generate(&mut self, ctx: &Section_Context) -> Result<Generator_Output, R::Error>
+  {
+    self(ctx.identifier).into_output()
+  }
+}
+
+impl<F, R> Generator<fn(&str, &Arguments)> for F
+where F: FnMut(&str, &Arguments) -> R,
+      R: Generator_Return,
+{
+  type Error = R::Error;
+
+  fn generate(&mut self, ctx: &Section_Context) -> Result<Generator_Output, R::Error>
+  {
+    self(ctx.identifier, &ctx.arguments).into_output()
+  }
+}
+
+impl<F, R> Generator<fn(&Section_Context)> for F
+where F: FnMut(&Section_Context) -> R,
+      R: Generator_Return,
+{
+  type Error = R::Error;
+
+  fn generate(&mut self, ctx: &Section_Context) -> Result<Generator_Output, R::Error>
+  {
+    self(ctx).into_output()
+  }
+}
+
+/// What a [`Generator`] returns for a section.
+///
+/// Either an `Option<String>`, where `None` means [`Generator_Output::NOT_MINE`],
+/// or a [`Generator_Output`]. Wrapped into a `Result`, the generator may fail
+/// with any error type.
+pub trait Generator_Return
+{
+  type Error;
+
+  fn into_output(self) -> Result<Generator_Output, Self::Error>;
+}
+
+impl Generator_Return for Option<String>
+{
+  type Error = std::fmt::Error;
+
+  fn into_output(self) -> Result<Generator_Output, Self::Error>
+  {
+    Ok(self.into())
+  }
+}
+
+impl Generator_Return for Generator_Output
+{
+  type Error = std::fmt::Error;
+
+  fn into_output(self) -> Result<Generator_Output, Self::Error>
+  {
+    Ok(self)
+  }
+}
+
+impl<T, E> Generator_Return for Result<T, E>
+where T: Into<Generator_Output>
+{
+  type Error = E;
+
+  fn into_output(self) -> Result<Generator_Output, E>
+  {
+    self.map(Into::into)
+  }
+}
+
+/// What a generator returns for a section, telling the sections it keeps apart
+/// from the sections it doesn't know (see [`Config::strict`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Generator_Output
+{
+  /// Replaces the code of the section
+  CODE(String),
+  /// The generator is responsible for the section, but keeps its code
+  KEEP,
+  /// The generator doesn't know the section. It's kept as it is, unless
+  /// [`Config::strict`] is set.
+  NOT_MINE,
+}
+
+impl From<Option<String>> for Generator_Output
+{
+  fn from(code: Option<String>) -> Self
+  {
+    match code
+    {
+      Some(code) => Generator_Output::CODE(code),
+      None => Generator_Output::NOT_MINE,
+    }
+  }
+}
+
+impl From<String> for Generator_Output
+{
+  fn from(code: String) -> Self
+  {
+    Generator_Output::CODE(code)
+  }
+}
+
+use super::*;
@@ -0,0 +1,319 @@
+/// An inconsistency of the markers of a section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint
+{
+  /// Points at the offending marker line
+  pub location: Location,
+  pub identifier: String,
+  pub kind: Lint_Kind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lint_Kind
+{
+  /// The text before the end marker differs from the one before the begin
+  /// marker, e.g. `/*` and `(*`. This is not fixed by [`format_markers`], as
+  /// the intended comment syntax is unknown.
+  BEFORE_MARKER_MISMATCH{begin: String, end: String},
+  /// The end marker is indented differently than its begin marker
+  END_MARKER_INDENTATION,
+  /// The marker line is not written like [`Config::marker_style`] would write
+  /// it, e.g. `<<codegen   foo>>`
+  NON_CANONICAL_MARKER{line: String, canonical: String},
+  /// The checksum is stored with another length than
+  /// [`Config::checksum_bytes_to_store`]. A longer checksum is only written
+  /// by [`format_markers`], if the code matches the stored checksum.
+  CHECKSUM_LENGTH{expected: usize, actual: usize},
+}
+
+/// Reports the marker inconsistencies of `sections` without changing anything.
+///
+/// `sections` is the result of [`crate::parse_file::find`] or
+/// [`crate::parse_file::find_nested`], whose nested sections are linted too.
+pub fn lint(sections: &Section_List, cfg: Config) -> Vec<Lint>
+{
+  let mut lints = Vec::new();
+  lint_sections(sections, cfg, 1, &mut lints);
+  lints
+}
+
+/// Lints `sections`, starting at the 1-based line `first_line`
+fn lint_sections(sections: &[Section], cfg: Config, first_line: usize, lints: &mut Vec<Lint>)
+{
+  use Section::*;
+
+  let mut line = first_line;
+  for sec in sections.iter()
+  {
+    match sec
+    {
+      HANDWRITTEN(code) => line += count_lines(code),
+      CODEGEN{identifier, arguments, code, checksum, checksum_algorithm, begin, end, children} =>
+      {
+        let begin_line = line;
+        let end_line = begin_line + 1 + count_lines(code);
+        line = end_line + 1;
+
+        let lint = |line, marker: &Marker, kind| Lint{location: Location::new(None, line, marker.column()), identifier: identifier.to_string(), kind};
+
+        if let Some(canonical) = canonical_begin_line(&cfg.marker_style, begin, identifier, arguments)
+        {
+          lints.push(lint(begin_line, begin, Lint_Kind::NON_CANONICAL_MARKER{line: begin.to_string(), canonical}));
+        }
+        lint_sections(children, cfg, begin_line+1, lints);
+        if begin.before_marker != end.before_marker
+        {
+          lints.push(lint(end_line, end, Lint_Kind::BEFORE_MARKER_MISMATCH{begin: begin.before_marker.to_owned(), end: end.before_marker.to_owned()}));
+        }
+        if begin.indentation != end.indentation
+        {
+          lints.push(lint(end_line, end, Lint_Kind::END_MARKER_INDENTATION));
+        }
+        let end_name = end_name(cfg, identifier, end);
+        let checksum_text = format_checksum(checksum, checksum.len(), *checksum_algorithm);
+        if let Some(canonical) = canonical_end_line(&cfg.marker_style, end, end_name, &checksum_text)
+        {
+          lints.push(lint(end_line, end, Lint_Kind::NON_CANONICAL_MARKER{line: end.to_string(), canonical}));
+        }
+        let expected = expected_checksum_len(cfg, *checksum_algorithm);
+        if expected != checksum.len()
+        {
+          lints.push(lint(end_line, end, Lint_Kind::CHECKSUM_LENGTH{expected, actual: checksum.len()}));
+        }
+      }
+      INLINE{identifier, arguments, code, checksum, checksum_algorithm, begin, end} =>
+      {
+        let inline_line = line;
+        line += 1;
+
+        let mut report = |kind| lints.push(Lint{location: Location::new(None, inline_line, begin.column()), identifier: identifier.to_string(), kind});
+
+        let end_name = end_name(cfg, identifier, end);
+        let checksum_text = format_checksum(checksum, checksum.len(), *checksum_algorithm);
+        let mut canonical = String::new();
+        cfg.marker_style.write_inline_line(&mut canonical, &Inline_Line{begin, identifier, arguments, code, end, end_name, checksum: &checksum_text}).expect("writing to a String can't fail");
+        let parsed = format!("{begin}{code}{end}");
+        if canonical != parsed
+        {
+          report(Lint_Kind::NON_CANONICAL_MARKER{line: parsed, canonical});
+        }
+        let expected = expected_checksum_len(cfg, *checksum_algorithm);
+        if expected != checksum.len()
+        {
+          report(Lint_Kind::CHECKSUM_LENGTH{expected, actual: checksum.len()});
+        }
+      }
+    }
+  }
+}
+
+/// Fixes the marker inconsistencies reported by [`lint`] without calling any
+/// generator.
+///
+/// The marker lines are rewritten with [`Config::marker_style`], end markers
+/// are indented like their begin markers and checksums are shortened or, if
+/// the code still matches them, lengthened. With [`Config::nesting`], the
+/// markers of nested sections are fixed too, and the checksums of the
+/// sections containing them are recomputed, if the code matched them. Returns
+/// `None` if nothing changed.
+pub fn format_markers(input: &str, cfg: Config) -> parse_file::Result<Option<String>>
+{
+  // Checksums without names are read as such, so the names are added
+  let sections = find_sections(input, &cfg.marker_style, cfg.nesting != Nesting::FORBIDDEN, false)?;
+  let formatted = format_sections(&sections, cfg, Line_Ending::detect(input), input.ends_with('\n'));
+
+  Ok(if formatted == input {None} else {Some(formatted)})
+}
+
+fn format_sections(sections: &[Section], cfg: Config, nl: Line_Ending, final_linebreak: bool) -> String
+{
+  use Section::*;
+
+  let mut formatted = String::new();
+  for (index, sec) in sections.iter().enumerate()
+  {
+    match sec
+    {
+      HANDWRITTEN(code) => formatted += code,
+      CODEGEN{identifier, arguments, code, checksum, checksum_algorithm, begin, end, children} =>
+      {
+        // The code of a section ends with a line break
+        let formatted_code = match children.is_empty()
+        {
+          true => code.to_string(),
+          false => format_sections(children, cfg, nl, true),
+        };
+        let unindented = begin.indentation.unindent_str(code, cfg.tab_width).ok();
+        let formatted_unindented = begin.indentation.unindent_str(&formatted_code, cfg.tab_width).ok();
+        let checksum_text = fixed_checksum(cfg, identifier, arguments, unindented.as_deref(), formatted_unindented.as_deref(), checksum, *checksum_algorithm)
+          .unwrap_or_else(|| format_checksum(checksum, checksum.len(), *checksum_algorithm));
+        let end_name = end_name(cfg, identifier, end);
+
+        cfg.marker_style.write_begin_line(&mut formatted, begin, identifier, arguments).expect("writing to a String can't fail");
+        formatted += nl.as_str();
+        formatted += &formatted_code;
+        cfg.marker_style.write_end_line(&mut formatted, &Marker{indentation: begin.indentation, ..*end}, end_name, &checksum_text).expect("writing to a String can't fail");
+        if final_linebreak || index+1 != sections.len()
+        {
+          formatted += nl.as_str();
+        }
+      }
+      INLINE{identifier, arguments, code, checksum, checksum_algorithm, begin, end} =>
+      {
+        let checksum_text = fixed_checksum(cfg, identifier, arguments, Some(code), Some(code), checksum, *checksum_algorithm)
+          .unwrap_or_else(|| format_checksum(checksum, checksum.len(), *checksum_algorithm));
+        let end_name = end_name(cfg, identifier, end);
+
+        cfg.marker_style.write_inline_line(&mut formatted, &Inline_Line{begin, identifier, arguments, code, end, end_name, checksum: &checksum_text}).expect("writing to a String can't fail");
+        if final_linebreak || index+1 != sections.len()
+        {
+          formatted += nl.as_str();
+        }
+      }
+    }
+  }
+  formatted
+}
+
+impl fmt::Display for Lint
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(f, "{}: section `{}` {}", self.location, self.identifier, self.kind)
+  }
+}
+
+impl fmt::Display for Lint_Kind
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    match self
+    {
+      Lint_Kind::BEFORE_MARKER_MISMATCH{begin, end} => write!(f, "has `{end}` before its end marker, but `{begin}` before its begin marker"),
+      Lint_Kind::END_MARKER_INDENTATION => write!(f, "has an end marker indented differently than its begin marker"),
+      Lint_Kind::NON_CANONICAL_MARKER{line, canonical} => write!(f, "has the marker line `{line}` instead of `{canonical}`"),
+      Lint_Kind::CHECKSUM_LENGTH{expected, actual} => write!(f, "has a checksum of {actual} bytes instead of {expected}"),
+    }
+  }
+}
+
+/// Returns the canonical line, if it differs from the parsed one
+fn canonical_begin_line(style: &Marker_Style, marker: &Marker, identifier: &str, arguments: &Arguments) -> Option<String>
+{
+  let mut canonical = String::new();
+  style.write_begin_line(&mut canonical, marker, identifier, arguments).expect("writing to a String can't fail");
+  (canonical != marker.to_string()).then_some(canonical)
+}
+
+/// Returns the canonical line, if it differs from the parsed one
+fn canonical_end_line(style: &Marker_Style, marker: &Marker, name: &str, checksum: &str) -> Option<String>
+{
+  let mut canonical = String::new();
+  style.write_end_line(&mut canonical, marker, name, checksum).expect("writing to a String can't fail");
+  (canonical != marker.to_string()).then_some(canonical)
+}
+
+fn expected_checksum_len(cfg: Config, algorithm: Option<Checksum_Algorithm>) -> usize
+{
+  algorithm.unwrap_or_default().stored_len(cfg.checksum_bytes_to_store)
+}
+
+/// The checksum text with the expected length for the formatted `code`, if it
+/// can be computed.
+///
+/// `old_code` and `code` are the unindented code before and after formatting
+/// the nested markers, if they could be unindented. A checksum is only
+/// computed, if the stored one matches `old_code`.
+fn fixed_checksum(cfg: Config, identifier: &str, arguments: &Arguments, old_code: Option<&str>, code: Option<&str>, checksum: &ArrayVec<u8, 32>, algorithm: Option<Checksum_Algorithm>) -> Option<String>
+{
+  let len = expected_checksum_len(cfg, algorithm);
+  if len <= checksum.len() && old_code == code
+  {
+    return Some(format_checksum(checksum, len, algorithm));
+  }
+
+  let verify_algorithm = algorithm.unwrap_or_default();
+  if verify_algorithm.is_keyed() && cfg.checksum_key.is_none()
+  {
+    return None;
+  }
+  let checksum_input = |code: &str| checksum_code(code, cfg).ok().map(|code| checksum_input(&code, identifier, arguments, cfg.checksum_covers_identifier).into_owned());
+  check_code_checksum(&checksum_input(old_code?)?, checksum, verify_algorithm, cfg.checksum_key).ok()?;
+  let actual = verify_algorithm.checksum_with_key(&checksum_input(code?)?, cfg.checksum_key)?;
+  Some(format_checksum(actual.as_bytes(), len, algorithm))
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  const CFG : Config = Config{checksum_bytes_to_store: 0, .. Config::DEFAULT};
+
+  #[test]
+  fn test_lint()
+  {
+    let lint = |input, cfg| super::lint(&parse_file::find(input, &Marker_Style::DEFAULT).unwrap_display(), cfg);
+    let at = |line, column, kind| Lint{location: Location::new(None, line, column), identifier: "foo".to_owned(), kind};
+
+    assert_eq!(lint("x\n// << codegen foo >>\n42\n// << /codegen >>\n", CFG), vec![]);
+    assert_eq!(lint("x\n/* << codegen foo >> */\n42\n(* << /codegen >> *)\n", CFG), vec![
+      at(4, 4, Lint_Kind::BEFORE_MARKER_MISMATCH{begin: "/* ".to_owned(), end: "(* ".to_owned()}),
+    ]);
+    assert_eq!(lint("  << codegen foo >>\n  42\n<< /codegen >>\n", CFG), vec![
+      at(3, 1, Lint_Kind::END_MARKER_INDENTATION),
+    ]);
+    assert_eq!(lint("<<codegen   foo>>\n42\n<</codegen   ab12 >>\n", CFG), vec![
+      at(1, 1, Lint_Kind::NON_CANONICAL_MARKER{line: "<<codegen   foo>>".to_owned(), canonical: "<< codegen foo >>".to_owned()}),
+      at(3, 1, Lint_Kind::NON_CANONICAL_MARKER{line: "<</codegen   ab12 >>".to_owned(), canonical: "<< /codegen ab12 >>".to_owned()}),
+      at(3, 1, Lint_Kind::CHECKSUM_LENGTH{expected: 0, actual: 2}),
+    ]);
+    assert_eq!(lint("<< codegen foo >>\n42\n<< /codegen crc32:d1862931 >>\n", Config{checksum_bytes_to_store: 32, ..CFG}), vec![]);
+    assert_eq!(lint("x\nint x[] = {/*<<codegen foo>>*/1/*<< /codegen ab >>*/};\n", CFG), vec![
+      at(2, 14, Lint_Kind::NON_CANONICAL_MARKER{line: "int x[] = {/*<<codegen foo>>*/1/*<< /codegen ab >>*/};".to_owned(), canonical: "int x[] = {/*<< codegen foo >>*/1/*<< /codegen ab >>*/};".to_owned()}),
+      at(2, 14, Lint_Kind::CHECKSUM_LENGTH{expected: 0, actual: 1}),
+    ]);
+
+    let nested = parse_file::find_nested("<< codegen bar >>\n  <<codegen foo>>\n  42\n<< /codegen >>\n<< /codegen >>\n", &Marker_Style::DEFAULT).unwrap_display();
+    assert_eq!(super::lint(&nested, CFG), vec![
+      at(2, 3, Lint_Kind::NON_CANONICAL_MARKER{line: "  <<codegen foo>>".to_owned(), canonical: "  << codegen foo >>".to_owned()}),
+      at(4, 1, Lint_Kind::END_MARKER_INDENTATION),
+    ]);
+  }
+
+  #[test]
+  fn test_format_markers()
+  {
+    const CHECKSUM : Config = Config{checksum_bytes_to_store: 4, .. CFG};
+
+    assert_eq!(format_markers("x\n// << codegen foo >>\n42\n// << /codegen >>", CFG).unwrap_display(), None);
+    assert_eq!(format_markers("x\n  //<<codegen   foo>>\n  42\n //<</codegen>>", CFG).unwrap_display(), Some("x\n  //<< codegen foo >>\n  42\n  //<< /codegen >>".to_owned()));
+    assert_eq!(format_markers("/* << codegen foo >> */\r\n42\r\n(* << /codegen >> *)\r\n", CFG).unwrap_display(), None);
+
+    // Shortened checksums are always fixed, longer ones only if the code matches
+    assert_eq!(format_markers("<< codegen foo >>\n42\n<< /codegen a16072b1c2 >>\n", CHECKSUM).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen a16072b1 >>\n".to_owned()));
+    assert_eq!(format_markers("<< codegen foo >>\n42\n<< /codegen a160 >>\n", CHECKSUM).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen a16072b1 >>\n".to_owned()));
+    assert_eq!(format_markers("<< codegen foo >>\n43\n<< /codegen a160 >>\n", CHECKSUM).unwrap_display(), None);
+    assert_eq!(format_markers("<< codegen foo >>\n42\n<< /codegen >>\n", Config{checksum_bytes_to_store: 2, ..CFG}).unwrap_display(), Some("<< codegen foo >>\n42\n<< /codegen a160 >>\n".to_owned()));
+    assert_eq!(format_markers("x = /*<<codegen foo>>*/42/*<</codegen>>*/;\n", Config{checksum_bytes_to_store: 2, ..CFG}).unwrap_display(), Some("x = /*<< codegen foo >>*/42/*<< /codegen da13 >>*/;\n".to_owned()));
+
+    // The checksum of the outer section covers the fixed nested markers
+    const NESTED : Config = Config{nesting: Nesting::OUTER_FIRST, expand_generated_sections: true, ..CHECKSUM};
+    let gen = |identifier: &str| match identifier
+    {
+      "outer" => Some("<< codegen inner >>\n<< /codegen >>\n".to_owned()),
+      _ => Some("42".to_owned()),
+    };
+    let generated = crate::generate("<< codegen outer >>\n<< /codegen >>\n", NESTED, gen).unwrap_display().unwrap();
+    let unformatted = generated.replace("<< codegen inner >>", "<<codegen   inner>>");
+    assert_eq!(format_markers(&unformatted, NESTED).unwrap_display(), Some(generated.clone()));
+    assert_eq!(crate::generate(&generated, NESTED, gen).unwrap_display(), None);
+    assert!(format_markers(&unformatted, CHECKSUM).is_err());
+    let edited = unformatted.replace("42", "43");
+    assert_eq!(format_markers(&edited, NESTED).unwrap_display(), Some(edited.replace("<<codegen   inner>>", "<< codegen inner >>")));
+  }
+}
+
+use super::*;
+use crate::parse_file::{self, Section_List};
+use std::fmt;
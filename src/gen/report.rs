@@ -0,0 +1,92 @@
+/// What [`generate_with_report`](crate::generate_with_report) did with each
+/// section.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Generate_Report
+{
+  /// In the order of the sections in the file
+  pub sections: Vec<Section_Report>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section_Report
+{
+  pub identifier: String,
+  /// 1-based line numbers of the begin and end marker in the input
+  pub old_lines: RangeInclusive<usize>,
+  /// 1-based line numbers of the begin and end marker in the output
+  pub new_lines: RangeInclusive<usize>,
+  pub status: Section_Status,
+  /// The checksum as written in the end marker, including its tag. Empty if
+  /// there is none.
+  pub old_checksum: String,
+  pub new_checksum: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section_Status
+{
+  /// The generator returned the code already in the file
+  UNCHANGED,
+  /// The code was replaced by the generated code
+  REGENERATED,
+  /// A hand edit was replaced by the generated code, see
+  /// [`Hand_Edit_Policy::OVERWRITE`](crate::Hand_Edit_Policy::OVERWRITE)
+  OVERWRITTEN,
+  /// The code is the same, but the checksum was rewritten with another
+  /// length, algorithm or mode
+  CHECKSUM_UPDATED,
+  /// The generator returned `None` or a hand edit was kept, so the section
+  /// is left as it is
+  KEPT,
+}
+
+impl Generate_Report
+{
+  /// The sections which were written differently
+  pub fn changed(&self) -> impl Iterator<Item=&Section_Report>
+  {
+    self.sections.iter().filter(|section| section.status.is_change())
+  }
+
+  /// The sections whose hand edit was overwritten, see
+  /// [`Hand_Edit_Policy::OVERWRITE`](crate::Hand_Edit_Policy::OVERWRITE)
+  pub fn overwritten(&self) -> impl Iterator<Item=&Section_Report>
+  {
+    self.sections.iter().filter(|section| section.status == Section_Status::OVERWRITTEN)
+  }
+}
+
+impl Section_Status
+{
+  pub fn is_change(self) -> bool
+  {
+    matches!(self, Section_Status::REGENERATED | Section_Status::OVERWRITTEN | Section_Status::CHECKSUM_UPDATED)
+  }
+}
+
+impl fmt::Display for Section_Report
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(f, "lines {}-{}: section `{}` {}", self.old_lines.start(), self.old_lines.end(), self.identifier, self.status)
+  }
+}
+
+impl fmt::Display for Section_Status
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    let status = match self
+    {
+      Section_Status::UNCHANGED => "is unchanged",
+      Section_Status::REGENERATED => "was regenerated",
+      Section_Status::OVERWRITTEN => "had its hand edit overwritten",
+      Section_Status::CHECKSUM_UPDATED => "got a new checksum",
+      Section_Status::KEPT => "was kept",
+    };
+    f.write_str(status)
+  }
+}
+
+use std::fmt;
+use std::ops::RangeInclusive;
@@ -0,0 +1,28 @@
+/// Everything a generator may want to know about the section it generates.
+#[derive(Clone, Debug)]
+pub struct Section_Context<'a>
+{
+  pub identifier: &'a str,
+  pub arguments: Arguments<'a>,
+  /// The previously generated code, without indentation and with `\n` line
+  /// endings
+  pub old_code: &'a str,
+  /// Empty for inline sections
+  pub indentation: Indentation<'a>,
+  pub begin: Marker<'a>,
+  pub end: Marker<'a>,
+  /// 0-based index of the section among its sibling sections, i.e. the
+  /// generated sections in the same enclosing section or at the top level of
+  /// the file
+  pub index: usize,
+  /// 1-based line number of the begin marker
+  pub line: usize,
+  /// The processed file, if known
+  pub path: Option<&'a Path>,
+  /// The identifier of the enclosing section, if this one is nested (see
+  /// [`Config::nesting`](crate::Config::nesting))
+  pub parent: Option<&'a str>,
+}
+
+use super::*;
+use std::path::Path;
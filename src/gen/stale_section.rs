@@ -0,0 +1,66 @@
+/// A section which would be changed by regenerating it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stale_Section
+{
+  pub identifier: String,
+  /// 1-based line numbers of the begin and end marker
+  pub lines: RangeInclusive<usize>,
+  pub reason: Stale_Reason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stale_Reason
+{
+  /// The generator returned different code
+  CONTENT_CHANGED,
+  /// The code is the same, but the checksum is stored with a different length
+  CHECKSUM_LENGTH_CHANGED,
+  /// The code is the same, but the checksum is computed with a different
+  /// algorithm
+  CHECKSUM_ALGORITHM_CHANGED,
+  /// The code is the same, but the checksum was written with a different
+  /// [`Config::checksum_covers_identifier`](crate::Config::checksum_covers_identifier).
+  /// Only accepted by [`migrate_checksums`](crate::migrate_checksums), else
+  /// the section is reported as hand-edited.
+  CHECKSUM_MODE_CHANGED,
+  /// The code was hand-edited since it was generated
+  CHECKSUM_MISMATCH,
+  /// The code was edited by someone without the checksum key
+  INVALID_MAC,
+}
+
+impl Stale_Reason
+{
+  pub fn is_hand_edit(self) -> bool
+  {
+    matches!(self, Stale_Reason::CHECKSUM_MISMATCH | Stale_Reason::INVALID_MAC)
+  }
+}
+
+impl fmt::Display for Stale_Section
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(f, "lines {}-{}: section `{}` {}", self.lines.start(), self.lines.end(), self.identifier, self.reason)
+  }
+}
+
+impl fmt::Display for Stale_Reason
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    let reason = match self
+    {
+      Stale_Reason::CONTENT_CHANGED => "is outdated",
+      Stale_Reason::CHECKSUM_LENGTH_CHANGED => "has a checksum of the wrong length",
+      Stale_Reason::CHECKSUM_ALGORITHM_CHANGED => "has a checksum of another algorithm",
+      Stale_Reason::CHECKSUM_MODE_CHANGED => "has a checksum of another mode",
+      Stale_Reason::CHECKSUM_MISMATCH => "was hand-edited (checksum mismatch)",
+      Stale_Reason::INVALID_MAC => "has an invalid MAC (edited without the checksum key)",
+    };
+    f.write_str(reason)
+  }
+}
+
+use std::fmt;
+use std::ops::RangeInclusive;
@@ -1,29 +1,114 @@
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub struct Indentation(pub usize);
+/// The whitespace prefix of a marker line.
+///
+/// May consist of spaces, tabs or both. Generated code inherits exactly this
+/// prefix.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Indentation<'a>(pub &'a str);
 
-impl Indentation
+impl Indentation<'_>
 {
   pub fn indent_str(self, text: &str) -> String
   {
     indent_lines(text, self.0)
   }
 
-  pub fn unindent_str(self, text: &str) -> Result<String, Unindent_Error>
+  /// Removes this indentation from each line of `text`.
+  ///
+  /// With a non-zero `tab_width`, a line may also start with a different mix
+  /// of tabs and spaces of the same width, e.g. after an editor converted its
+  /// indentation. A tab reaching past this indentation keeps the rest of its
+  /// width as spaces.
+  pub fn unindent_str(self, text: &str, tab_width: u8) -> Result<String, Unindent_Error>
   {
-    unindent_lines(text, self.0)
+    unindent_lines(text, self.0, tab_width)
+  }
+
+  pub fn uses_tabs(self) -> bool
+  {
+    self.0.contains('\t')
+  }
+
+  /// Converts the leading whitespace of each line in `text` to the style of
+  /// this indentation.
+  ///
+  /// If the indentation contains a tab, leading spaces are converted to tabs.
+  /// If it consists of spaces only, leading tabs are expanded to spaces.
+  /// An empty indentation, like the one of a marker in column 0, has no
+  /// style, so the style of the surrounding file `file_style` is used. Without
+  /// any style or with a `tab_width` of 0, the text is left untouched.
+  pub fn adapt_leading_whitespace(self, text: String, tab_width: u8, file_style: Option<Indent_Style>) -> String
+  {
+    let use_tabs = match (self.0.is_empty(), file_style)
+    {
+      (false, _) => self.uses_tabs(),
+      (true, Some(style)) => style == Indent_Style::TABS,
+      (true, None) => return text,
+    };
+    if tab_width == 0
+    {
+      return text;
+    }
+
+    let tab_width = tab_width as usize;
+
+    let mut output = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n')
+    {
+      let content = line.trim_start_matches([' ', '\t']);
+      let leading = &line[..line.len()-content.len()];
+
+      let column = indentation_width(leading, tab_width);
+
+      let (tabs, spaces) = match use_tabs
+      {
+        true => (column / tab_width, column % tab_width),
+        false => (0, column),
+      };
+      output.extend(std::iter::repeat('\t').take(tabs));
+      output.extend(std::iter::repeat(' ').take(spaces));
+      output.push_str(content);
+    }
+
+    output
   }
 }
 
-fn indent_lines(input: &str, indentation: usize) -> String
+/// Whether the indented lines of a file start with tabs or spaces
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Indent_Style
 {
-  let mut output = String::with_capacity((input.len()+1)*(indentation+1));
+  TABS,
+  SPACES,
+}
+
+impl Indent_Style
+{
+  /// Returns the style used by most indented lines of `text`.
+  ///
+  /// `None` if no line is indented or on ties.
+  pub fn detect(text: &str) -> Option<Self>
+  {
+    let tabs = text.lines().filter(|line| line.starts_with('\t')).count();
+    let spaces = text.lines().filter(|line| line.starts_with(' ')).count();
+
+    match tabs.cmp(&spaces)
+    {
+      Ordering::Greater => Some(Indent_Style::TABS),
+      Ordering::Less => Some(Indent_Style::SPACES),
+      Ordering::Equal => None,
+    }
+  }
+}
+
+fn indent_lines(input: &str, indentation: &str) -> String
+{
+  let mut output = String::with_capacity(input.len() + (input.len()/8+1)*indentation.len());
 
-  let indentation = std::iter::repeat(' ').take(indentation);
   for line in input.lines()
   {
     if !line.is_empty()
     {
-      output.extend(indentation.clone());
+      output.push_str(indentation);
       output.push_str(line);
     }
     output.push('\n');
@@ -32,39 +117,118 @@ fn indent_lines(input: &str, indentation: usize) -> String
   output
 }
 
-fn unindent_lines(input: &str, indentation: usize) -> Result<String, Unindent_Error>
+fn unindent_lines(input: &str, indentation: &str, tab_width: u8) -> Result<String, Unindent_Error>
 {
   let mut output = String::with_capacity(input.len());
 
-  for line in input.lines()
+  for (line_index, line) in input.lines().enumerate()
   {
-    let keep_after = line.len().min(indentation);
-    if !line.as_bytes()[..keep_after].iter().all(|&x| x==b' ')
-    { return Err(Unindent_Error::NON_WS_IN_INDENTATION) }
-    output.push_str(&line[keep_after..]);
+    let line = match line.strip_prefix(indentation)
+    {
+      Some(line) => Cow::Borrowed(line),
+      None if indentation.starts_with(line) => Cow::Borrowed(""),
+      None => match strip_indentation_width(line, indentation, tab_width)
+      {
+        Some(line) => line,
+        None =>
+        {
+          let matching = line.chars().zip(indentation.chars()).take_while(|(a, b)| a == b).count();
+          let line_number = line_index + 1;
+          let column = matching + 1;
+          return Err(match line[line.char_indices().nth(matching).unwrap().0..].starts_with([' ', '\t'])
+          {
+            true => Unindent_Error::MISMATCHED_WS_IN_INDENTATION{line: line_number, column},
+            false => Unindent_Error::NON_WS_IN_INDENTATION{line: line_number, column},
+          });
+        }
+      }
+    };
+    output.push_str(&line);
     output.push('\n');
   }
 
   Ok(output)
 }
 
-impl fmt::Display for Indentation
+/// Strips leading whitespace as wide as `indentation` from `line`, assuming
+/// tabs of `tab_width`. `None` if `tab_width` is 0 or the line is indented less.
+fn strip_indentation_width<'a>(line: &'a str, indentation: &str, tab_width: u8) -> Option<Cow<'a, str>>
 {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  if tab_width == 0
   {
-    for _ in 0..self.0
+    return None;
+  }
+
+  let width = indentation_width(indentation, tab_width as usize);
+  let mut column = 0;
+  for (pos, c) in line.char_indices()
+  {
+    if column == width
+    {
+      return Some(Cow::Borrowed(&line[pos..]));
+    }
+    column = match c
+    {
+      ' ' => column + 1,
+      '\t' => (column/tab_width as usize + 1) * tab_width as usize,
+      _ => return None,
+    };
+    if column > width
     {
-      f.write_char(' ')?;
+      let spaces = " ".repeat(column - width);
+      return Some(Cow::Owned(spaces + &line[pos+1..]));
     }
-    Ok(())
+  }
+
+  // Like with an exact prefix, a line of whitespace only may be shorter
+  Some(Cow::Borrowed(""))
+}
+
+/// The column reached by the whitespace `leading`, assuming tabs of `tab_width`
+fn indentation_width(leading: &str, tab_width: usize) -> usize
+{
+  leading.chars().fold(0, |column, c| match c
+  {
+    '\t' => (column/tab_width + 1) * tab_width,
+    _ => column + 1,
+  })
+}
+
+impl fmt::Display for Indentation<'_>
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    f.write_str(self.0)
   }
 }
 
+/// `line` and `column` are 1-based and relative to the unindented text
 #[derive(Clone, Debug, PartialEq, Eq, Error)]
 pub enum Unindent_Error
 {
   #[error("Non whitespace character in indentation")]
-  NON_WS_IN_INDENTATION,
+  NON_WS_IN_INDENTATION{line: usize, column: usize},
+  #[error("Indentation mixes tabs and spaces differently than the marker")]
+  MISMATCHED_WS_IN_INDENTATION{line: usize, column: usize},
+}
+
+impl Unindent_Error
+{
+  pub fn line(&self) -> usize
+  {
+    match *self
+    {
+      Unindent_Error::NON_WS_IN_INDENTATION{line, ..} | Unindent_Error::MISMATCHED_WS_IN_INDENTATION{line, ..} => line,
+    }
+  }
+
+  pub fn column(&self) -> usize
+  {
+    match *self
+    {
+      Unindent_Error::NON_WS_IN_INDENTATION{column, ..} | Unindent_Error::MISMATCHED_WS_IN_INDENTATION{column, ..} => column,
+    }
+  }
 }
 
 pub fn ensure_tailing_linebreak(mut xs: String) -> String
@@ -88,7 +252,7 @@ mod test
 
   macro_rules! unindent {
     ($i:expr, $str:expr) => {
-      crate::indentation::Indentation($i).unindent_str($str).unwrap().as_str()
+      crate::indentation::Indentation($i).unindent_str($str, 0).unwrap().as_str()
     };
   }
 
@@ -102,46 +266,110 @@ mod test
   #[test]
   fn test_trivial()
   {
-    assert_indent!(0, "", "");
-    assert_indent!(0, "x", "x\n");
-    assert_indent!(0, "x\ny", "x\ny\n");
+    assert_indent!("", "", "");
+    assert_indent!("", "x", "x\n");
+    assert_indent!("", "x\ny", "x\ny\n");
   }
 
   #[test]
   fn test_simpl()
   {
-    assert_indent!(2, "", "");
-    assert_indent!(2, "x", "  x\n");
-    assert_indent!(2, "x\ny", "  x\n  y\n");
-    assert_indent!(4, "Hello, World!", "    Hello, World!\n");
+    assert_indent!("  ", "", "");
+    assert_indent!("  ", "x", "  x\n");
+    assert_indent!("  ", "x\ny", "  x\n  y\n");
+    assert_indent!("    ", "Hello, World!", "    Hello, World!\n");
   }
 
   #[test]
   fn test_with_lienbreak()
   {
-    assert_indent!(2, "x\ny\nz", "  x\n  y\n  z\n");
+    assert_indent!("  ", "x\ny\nz", "  x\n  y\n  z\n");
   }
 
   #[test]
   fn test_dont_add_trailing_whitespace()
   {
-    assert_indent!(2, "x\n\n\ny", "  x\n\n\n  y\n");
-    assert_indent!(2, "x\n\ny\n\n\nz", "  x\n\n  y\n\n\n  z\n");
-    assert_indent!(2, "x\n", "  x\n");
+    assert_indent!("  ", "x\n\n\ny", "  x\n\n\n  y\n");
+    assert_indent!("  ", "x\n\ny\n\n\nz", "  x\n\n  y\n\n\n  z\n");
+    assert_indent!("  ", "x\n", "  x\n");
   }
 
   #[test]
   fn test_difficult_cases()
   {
-    assert_indent!(2, "\nx", "\n  x\n");
+    assert_indent!("  ", "\nx", "\n  x\n");
+  }
+
+  #[test]
+  fn test_tabs()
+  {
+    assert_indent!("\t", "x\n\ty", "\tx\n\t\ty\n");
+    assert_indent!("\t  ", "x\ny", "\t  x\n\t  y\n");
+    assert_eq!(unindent!("\t\t", "\t\n\t\tx\n"), "\nx\n");
   }
 
   #[test]
   fn test_unindent_invalid_indentation()
   {
-    assert_eq!(crate::indentation::Indentation(2).unindent_str("xyz"), Err(crate::indentation::Unindent_Error::NON_WS_IN_INDENTATION));
-    assert_eq!(crate::indentation::Indentation(2).unindent_str(" \n").unwrap(), "\n");
+    use crate::indentation::{Indentation, Unindent_Error};
+
+    assert_eq!(Indentation("  ").unindent_str("xyz", 0), Err(Unindent_Error::NON_WS_IN_INDENTATION{line: 1, column: 1}));
+    assert_eq!(Indentation("  ").unindent_str(" \n", 0).unwrap(), "\n");
+    assert_eq!(Indentation("  ").unindent_str("  x\n y", 0), Err(Unindent_Error::NON_WS_IN_INDENTATION{line: 2, column: 2}));
+    assert_eq!(Indentation("\t").unindent_str("  x", 0), Err(Unindent_Error::MISMATCHED_WS_IN_INDENTATION{line: 1, column: 1}));
+    assert_eq!(Indentation("  ").unindent_str("\t x", 0), Err(Unindent_Error::MISMATCHED_WS_IN_INDENTATION{line: 1, column: 1}));
+  }
+
+  #[test]
+  fn test_unindent_with_tab_width()
+  {
+    use crate::indentation::{Indentation, Unindent_Error};
+
+    // The same width written with tabs or spaces
+    assert_eq!(Indentation("\t").unindent_str("    x\n\ty\n", 4).unwrap(), "x\ny\n");
+    assert_eq!(Indentation("    ").unindent_str("\tx\n\t\ty\n", 4).unwrap(), "x\n\ty\n");
+    assert_eq!(Indentation("\t  ").unindent_str("      x\n  \t  y\n", 4).unwrap(), "x\ny\n");
+    // A tab reaching past the indentation keeps its remaining width
+    assert_eq!(Indentation("  ").unindent_str("\tx\n", 4).unwrap(), "  x\n");
+    assert_eq!(Indentation("\t").unindent_str("  \n", 4).unwrap(), "\n");
+
+    assert_eq!(Indentation("\t").unindent_str("  x", 4), Err(Unindent_Error::MISMATCHED_WS_IN_INDENTATION{line: 1, column: 1}));
+    assert_eq!(Indentation("    ").unindent_str("  x", 4), Err(Unindent_Error::NON_WS_IN_INDENTATION{line: 1, column: 3}));
+  }
+
+  #[test]
+  fn test_adapt_leading_whitespace()
+  {
+    use crate::indentation::Indentation;
+
+    use crate::indentation::Indent_Style;
+
+    let adapt = |i, text: &str, tab_width| Indentation(i).adapt_leading_whitespace(text.to_owned(), tab_width, None);
+
+    assert_eq!(adapt("\t", "x\n    y\n      z\n", 4), "x\n\ty\n\t  z\n");
+    assert_eq!(adapt("  ", "x\n\ty\n  \tz\n", 4), "x\n    y\n    z\n");
+    assert_eq!(adapt("  ", "x\n\ty\n", 0), "x\n\ty\n");
+    assert_eq!(adapt("", "x\n\ty\n", 4), "x\n\ty\n");
+    assert_eq!(adapt("\t", "a  b\n", 4), "a  b\n");
+
+    // A marker in column 0 takes the style of the file
+    assert_eq!(Indentation("").adapt_leading_whitespace("all:\n    echo hi\n".to_owned(), 4, Some(Indent_Style::TABS)), "all:\n\techo hi\n");
+    assert_eq!(Indentation("").adapt_leading_whitespace("x\n\ty\n".to_owned(), 4, Some(Indent_Style::SPACES)), "x\n    y\n");
+    assert_eq!(Indentation("  ").adapt_leading_whitespace("x\n    y\n".to_owned(), 4, Some(Indent_Style::TABS)), "x\n    y\n");
+  }
+
+  #[test]
+  fn test_indent_style()
+  {
+    use crate::indentation::Indent_Style;
+
+    assert_eq!(Indent_Style::detect("all:\n\techo 42\n\techo 137\n"), Some(Indent_Style::TABS));
+    assert_eq!(Indent_Style::detect("fn x()\n{\n  42\n}\n"), Some(Indent_Style::SPACES));
+    assert_eq!(Indent_Style::detect("x\ny\n"), None);
+    assert_eq!(Indent_Style::detect("\tx\n  y\n"), None);
   }
 }
 
-use std::fmt::{self, Write};
\ No newline at end of file
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
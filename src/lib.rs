@@ -1,4 +1,5 @@
 #![allow(non_camel_case_types)]
+#![allow(clippy::needless_return, clippy::items_after_test_module, clippy::upper_case_acronyms)]
 
 /*!
 
@@ -51,7 +52,8 @@ In our example, the function will be called once for each section.
 Each time it accepts the section name (in our case `foo`, `bar` or `baz`) and
 returns the generated code for this section.
 If the section is not its responsibility, it can also return Ok(None) and the
-previous content will be used again.
+previous content will be used again. See [`Generator`] for the other kinds of
+functions and closures accepted as generator.
 
 In our example, the generator function `gen_code_lines` returns new code for the
 sections `foo` and `bar` while leaving `baz` untouched.
@@ -110,6 +112,7 @@ fn main() -> codebiber::Result
     // Anything checksum length other than 0 will catch unintended modifications
     // since the last modification.
     checksum_bytes_to_store: 3,
+    ..Default::default()
   };
 
   let actual_output = codebiber::generate(INPUT, cfg, gen_code_lines)?;
@@ -157,7 +160,10 @@ void handwritten_line5();
 
 Please not how the generated code in the `bar` section is indented.
 That's because the generated code inherits the indentation of the marker line
-`// << codegen bar >>`
+`// << codegen bar >>`. The indentation may consist of spaces, tabs or both.
+Set [`Config::tab_width`] to convert the leading whitespace of the generated
+code to the style of the marker's indentation. Markers in column 0, like in
+Makefiles, take the style most indented lines of the file use.
 
 # Example 2
 
@@ -188,6 +194,7 @@ fn main() -> codebiber::Result
     // Anything checksum length other than 0 will catch unintended modifications
     // since the last modification.
     checksum_bytes_to_store: 2,
+    ..Default::default()
   };
 
   let actual_output = codebiber::generate(INPUT, cfg, gen_code_lines)?;
@@ -233,26 +240,308 @@ void autogen_line_bar2();
 Also note how every `bar` section shares the same hashsum. Thats because the
 hashsum is generated before indenting the code.
 
+# Arguments
+
+Begin markers may pass arguments to the generator, either in parentheses or
+separated by spaces. A generator taking them as second parameter receives them.
+
+```rust
+extern crate codebiber;
+
+const INPUT : &str = r#"
+// << codegen table(users, columns="id,name") >>
+// << /codegen >>
+// << codegen table groups columns=id >>
+// << /codegen >>
+"#;
+
+fn main() -> codebiber::Result
+{
+  let actual_output = codebiber::generate(INPUT, codebiber::Config::DEFAULT, |_: &str, arguments: &codebiber::Arguments| {
+    let name = arguments.positional().next().unwrap_or("unnamed");
+    let columns = arguments.get("columns").unwrap_or("");
+    Some(format!("CREATE TABLE {name} ({columns});"))
+  })?;
+
+  assert_eq!(actual_output, Some(EXPECTED_OUTPUT.to_owned()));
+
+  Ok(())
+}
+
+const EXPECTED_OUTPUT : &str = r#"
+// << codegen table(users, columns="id,name") >>
+CREATE TABLE users (id,name);
+// << /codegen >>
+// << codegen table groups columns=id >>
+CREATE TABLE groups (id);
+// << /codegen >>
+"#;
+```
+
+# Section context
+
+A generator may also take a [`Section_Context`]. Besides the identifier and arguments, it
+contains the previous code of the section, the marker lines, the position of the
+section and the processed file. This allows emitting code depending on the file
+or merging with the previous content.
+
+```rust
+extern crate codebiber;
+
+let input = "// << codegen log >>\nfirst\n// << /codegen >>\n";
+
+let output = codebiber::generate(input, codebiber::Config::DEFAULT, |ctx: &codebiber::Section_Context| {
+  Some(format!("{}line {}", ctx.old_code, ctx.line))
+}).unwrap();
+
+assert_eq!(output, Some("// << codegen log >>\nfirst\nline 1\n// << /codegen >>\n".to_owned()));
+```
+
+# Generator errors
+
+Generators returning a `Result` may fail with any error type. The error is passed on as [`gen::Gen_Error::GENERATOR`]
+together with the identifier and the location of the section.
+
+```rust
+extern crate codebiber;
+use codebiber::{gen::Gen_Error, Location};
+
+let output = codebiber::generate("// << codegen users >>\n// << /codegen >>\n", codebiber::Config::DEFAULT, |name: &str| -> Result<Option<String>, String> {
+  Err(format!("schema for `{name}` is missing"))
+});
+
+assert_eq!(output, Err(Gen_Error::GENERATOR{location: Location::new(None, 1, 4), identifier: "users".to_owned(), error: "schema for `users` is missing".to_owned()}));
+assert_eq!(output.unwrap_err().to_string(), "line 1: the generator failed for section `users`: schema for `users` is missing");
+```
+
+# Strict mode
+
+A generator returning `None` leaves the section as it is, which also hides
+typos like `<< codegen fooo >>`. Generators returning a [`Generator_Output`]
+instead tell sections they keep apart from sections they don't know. With
+[`Config::strict`], the latter fail with [`gen::Gen_Error::UNCLAIMED_SECTIONS`].
+
+```rust
+extern crate codebiber;
+use codebiber::{Config, Generator_Output};
+
+let cfg = Config{strict: true, ..Config::DEFAULT};
+let output = codebiber::generate("// << codegen fooo >>\n// << /codegen >>\n", cfg, |name: &str| match name {
+  "foo" => Generator_Output::CODE("42".to_owned()),
+  "legacy" => Generator_Output::KEEP,
+  _ => Generator_Output::NOT_MINE,
+});
+
+assert_eq!(output.unwrap_err().to_string(), "no generator claimed the sections `fooo` (line 1)");
+```
+
+# Nested sections
+
+With [`Config::nesting`], sections may contain sections, e.g. an `impl` block
+generated from one source whose body is filled from another.
+[`Nesting::OUTER_FIRST`] and [`Nesting::DEPTH_FIRST`] choose which generator is
+called first. The nested sections are generated where the outer generator keeps
+its code. With [`Config::expand_generated_sections`], the code of the outer
+generator may contain empty nested sections, which are then filled by their own
+generators or keep their previous code.
+
+```rust
+extern crate codebiber;
+use codebiber::{Config, Nesting};
+
+let cfg = Config{nesting: Nesting::OUTER_FIRST, expand_generated_sections: true, ..Config::DEFAULT};
+let input = "<< codegen impl_foo >>\n<< /codegen >>\n";
+let output = codebiber::generate(input, cfg, |name: &str| match name {
+  "impl_foo" => Some("impl Foo\n{\n  << codegen methods >>\n  << /codegen >>\n}".to_owned()),
+  "methods" => Some("fn foo() {}".to_owned()),
+  _ => None,
+}).unwrap();
+
+assert_eq!(output, Some("<< codegen impl_foo >>\nimpl Foo\n{\n  << codegen methods >>\n  fn foo() {}\n  << /codegen >>\n}\n<< /codegen >>\n".to_owned()));
+```
+
+Sections introduced by generated code are expanded in the same run, until no
+new sections appear. Sections expanding into themselves fail with
+[`gen::Gen_Error::EXPANSION_CYCLE`], and [`Config::max_nesting_depth`] limits
+how deep sections may be nested.
+
+# User regions
+
+With [`Config::user_regions`], parts of a section can be left to the user, e.g.
+the body of a generated method. The generator writes `<< keep name >>` and
+`<< /keep >>` markers and their content is kept when the section is
+regenerated. It's not covered by the
+checksum, so editing it is no hand edit. A user region missing in the
+generated code fails with [`gen::Gen_Error::USER_REGION`] instead of losing
+its content. If the generator moves the begin marker to another indentation,
+the content is re-indented along with it.
+
+```rust
+extern crate codebiber;
+use codebiber::Config;
+
+let cfg = Config{user_regions: true, ..Config::DEFAULT};
+let input = "<< codegen foo >>\nfn foo()\n{\n  // << keep body >>\n  bar();\n  // << /keep >>\n}\n<< /codegen >>\n";
+let output = codebiber::generate(input, cfg, |_: &str| Some("fn foo(x: u32)\n{\n  // << keep body >>\n  // << /keep >>\n}".to_owned())).unwrap().unwrap();
+
+assert!(output.contains("fn foo(x: u32)\n{\n  // << keep body >>\n  bar();\n"));
+```
+
+# Inline sections
+
+A section may also be a fragment of a single line, with both markers on that
+line. Comment delimiters around the markers (`/* */`, `<!-- -->`, `(* *)`,
+`{- -}` and `{# #}`) belong to the markers. The generated code must be a single
+line and isn't indented. A line may contain only one inline section, a second
+one fails with
+[`Parse_Error::MULTIPLE_INLINE_SECTIONS`](parse_file::Parse_Error::MULTIPLE_INLINE_SECTIONS).
+
+```rust
+extern crate codebiber;
+
+let input = "int sizes[] = {/*<< codegen sizes >>*/0/*<< /codegen >>*/};\n";
+let output = codebiber::generate(input, codebiber::Config::DEFAULT, |_: &str| Some("1,2,3".to_owned())).unwrap();
+
+assert_eq!(output, Some("int sizes[] = {/*<< codegen sizes >>*/1,2,3/*<< /codegen >>*/};\n".to_owned()));
+```
+
+Conflict markers don't fit into a line, so a hand-edited inline section fails
+with [`Hand_Edit_Policy::CONFLICT`] like with [`Hand_Edit_Policy::ERROR`].
+
+# Marker styles
+
+The tokens of the marker lines can be configured with [`Config::marker_style`].
+This is useful for languages using `<<` as an operator or to follow existing
+conventions. See [`Marker_Style`] for the presets. As `<<` is an operator in
+many languages, a line is only a marker line if its first `<<` starts the
+marker, so `std::cout << x; // << codegen foo >>` is handwritten code (see
+[`Marker_Style::open_is_operator`]).
+
+```rust
+extern crate codebiber;
+use codebiber::{Config, Marker_Style};
+
+let cfg = Config{marker_style: Marker_Style::REGION, ..Config::DEFAULT};
+
+let output = codebiber::generate("#region gen:foo\n#endregion gen\n", cfg, |_: &str| Some("42".to_owned())).unwrap();
+
+assert_eq!(output, Some("#region gen:foo\n42\n#endregion gen\n".to_owned()));
+```
+
+Changed sections get their marker lines rewritten in this style. To copy them
+byte-for-byte instead, replacing only the checksum, set
+[`Config::preserve_marker_lines`].
+
+End markers may repeat the identifier, like `<< /codegen foo >>` or
+`<< /codegen foo ab12 >>`. The parser reports a named end marker closing
+another section, e.g. after a deleted begin marker, as
+[`parse_file::Parse_Error::MISMATCHED_END_MARKER`]. With
+[`Config::named_end_markers`], `generate` writes the names and a single
+untagged token like `<< /codegen cafe >>` is a name, so checksums must follow
+the name or be tagged. Without it, the token is a name, if it's the identifier
+of the section or can't be a checksum, and a checksum otherwise.
+[`format_markers`] adds the missing names.
+
+[`lint`] reports inconsistent markers, like end markers indented differently
+than their begin markers or checksums of the wrong length, and
+[`format_markers`] fixes them without calling any generator.
+
+# Checking for stale sections
+
+[`check`] and [`check_files`] run the generators without writing anything and
+report which sections would change and why. This is useful for CI.
+
+```rust
+extern crate codebiber;
+use codebiber::{Stale_Section, Stale_Reason};
+
+let input = "// << codegen answer >>\n41\n// << /codegen >>\n";
+
+let stale = codebiber::check(input, codebiber::Config::DEFAULT, |_: &str| Some("42".to_owned())).unwrap();
+
+assert_eq!(stale, vec![Stale_Section{identifier: "answer".to_owned(), lines: 1..=3, reason: Stale_Reason::CONTENT_CHANGED}]);
+```
+
+# Diffs
+
+[`diff`] and [`diff_files`] return a unified diff of the sections which would
+change, with one hunk per section annotated with its identifier. With
+[`Diff_Mode::WRITE`], [`diff_files`] also writes the regenerated files.
+
+```rust
+extern crate codebiber;
+
+let input = "// << codegen answer >>\n41\n// << /codegen >>\n";
+
+let diff = codebiber::diff(input, codebiber::Config::DEFAULT, |_: &str| Some("42".to_owned())).unwrap();
+
+assert_eq!(diff.unwrap(), "\
+--- input
++++ input
+@@ -1,3 +1,3 @@ answer
+ // << codegen answer >>
+-41
++42
+ // << /codegen >>
+");
+```
+
+# Checksum algorithms
+
+The checksums are computed with [`Config::checksum_algorithm`]. Except for
+blake3, the end marker is tagged with the algorithm, like
+`<< /codegen sha256:ab12 >>`. Checksums are verified with the algorithm they are
+tagged with, so [`migrate_checksums`] can convert files between algorithms.
+
+```rust
+extern crate codebiber;
+use codebiber::{Config, Checksum_Algorithm};
+
+let cfg = Config{checksum_bytes_to_store: 4, checksum_algorithm: Checksum_Algorithm::CRC32, ..Config::DEFAULT};
+
+let output = codebiber::migrate_checksums("// << codegen foo >>\n42\n// << /codegen a160 >>\n", cfg).unwrap();
+
+assert_eq!(output, Some("// << codegen foo >>\n42\n// << /codegen crc32:d1862931 >>\n".to_owned()));
+```
+
+Plain checksums only detect accidental edits, as anyone can recompute them.
+[`Checksum_Algorithm::BLAKE3_MAC`] uses blake3's keyed mode with the
+[`Config::checksum_key`] instead. Sections edited without the key are reported
+as [`gen::Gen_Error::INVALID_MAC`], as are non-empty sections whose MAC is
+missing, shorter than 16 bytes or replaced by a plain checksum. Use
+[`migrate_checksums`] to add MACs to an existing file.
+
+# Line endings
+
+Files may use `\n` or `\r\n` line endings. The generated code and the marker
+lines are written with the line ending used by most lines of the input file.
+The hashsum is always computed with `\n` line endings, so converting a file
+between both styles keeps the hashsums valid.
+
 */
 
 pub mod parse_file;
+pub mod checksum;
 pub mod indentation;
+pub mod line_ending;
+pub mod location;
 pub mod process;
 pub mod gen;
+mod unified_diff;
 
 pub use indentation::Indentation;
-pub use gen::{generate, Config, Fmt_Result};
-pub use process::{process_file, process_files, Process_Error as Error, Result};
+pub use line_ending::Line_Ending;
+pub use location::Location;
+pub use checksum::{Checksum, Checksum_Algorithm, Checksum_Key};
+pub use parse_file::{Marker_Style, Arguments, Argument};
+pub use gen::{generate, generate_with_report, Generate_Report, Section_Report, Section_Status, migrate_checksums, check, diff, lint, format_markers, Lint, Lint_Kind, Config, Hand_Edit_Policy, Nesting, Fmt_Result, Gen_Result, Generator, Generator_Return, Generator_Output, Unclaimed_Section, Section_Context, Stale_Section, Stale_Reason};
+pub use process::{process_file, process_files, process_file_with_report, process_files_with_report, File_Report, check_file, check_files, diff_file, diff_files, Diff_Mode, Stale_File, Process_Error as Error, Result};
 
 extern crate blake3;
 
 extern crate arrayvec;
 use arrayvec::ArrayVec;
 
-extern crate pest;
-#[macro_use]
-extern crate pest_derive;
-
 #[macro_use]
 extern crate smallvec;
 use smallvec::SmallVec;
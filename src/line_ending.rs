@@ -0,0 +1,91 @@
+/// The line ending used when writing generated code and marker lines.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum Line_Ending
+{
+  #[default]
+  LF,
+  CRLF,
+}
+
+impl Line_Ending
+{
+  /// Returns the line ending used by most lines of `text`.
+  ///
+  /// Ties (including text without any line break) resolve to [`Line_Ending::LF`].
+  pub fn detect(text: &str) -> Self
+  {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+
+    match crlf > lf
+    {
+      true => Line_Ending::CRLF,
+      false => Line_Ending::LF,
+    }
+  }
+
+  pub fn as_str(self) -> &'static str
+  {
+    match self
+    {
+      Line_Ending::LF => "\n",
+      Line_Ending::CRLF => "\r\n",
+    }
+  }
+
+  /// Converts text using `\n` line breaks to this line ending.
+  pub fn apply(self, text: String) -> String
+  {
+    match self
+    {
+      Line_Ending::LF => text,
+      Line_Ending::CRLF => text.replace('\n', "\r\n"),
+    }
+  }
+}
+
+/// Replaces every `\r\n` by `\n`.
+pub fn normalize(text: String) -> String
+{
+  match text.contains("\r\n")
+  {
+    true => text.replace("\r\n", "\n"),
+    false => text,
+  }
+}
+
+impl fmt::Display for Line_Ending
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    f.write_str(self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  #[test]
+  fn test_detect()
+  {
+    assert_eq!(Line_Ending::detect(""), Line_Ending::LF);
+    assert_eq!(Line_Ending::detect("xyz"), Line_Ending::LF);
+    assert_eq!(Line_Ending::detect("x\ny\n"), Line_Ending::LF);
+    assert_eq!(Line_Ending::detect("x\r\ny\r\n"), Line_Ending::CRLF);
+    assert_eq!(Line_Ending::detect("x\r\ny\r\nz\n"), Line_Ending::CRLF);
+    assert_eq!(Line_Ending::detect("x\r\ny\nz\n"), Line_Ending::LF);
+    assert_eq!(Line_Ending::detect("x\r\ny\n"), Line_Ending::LF);
+  }
+
+  #[test]
+  fn test_convert()
+  {
+    assert_eq!(Line_Ending::CRLF.apply("x\ny\n".to_owned()), "x\r\ny\r\n");
+    assert_eq!(Line_Ending::LF.apply("x\ny\n".to_owned()), "x\ny\n");
+    assert_eq!(normalize("x\r\ny\n\r".to_owned()), "x\ny\n\r");
+  }
+}
+
+use std::fmt;
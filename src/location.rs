@@ -0,0 +1,52 @@
+/// Where in a file an error was found
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location
+{
+  /// The file, if known
+  pub path: Option<PathBuf>,
+  /// 1-based line number
+  pub line: usize,
+  /// 1-based column, counted in characters
+  pub column: usize,
+}
+
+impl Location
+{
+  pub fn new(path: Option<&Path>, line: usize, column: usize) -> Self
+  {
+    Location{path: path.map(Path::to_owned), line, column}
+  }
+
+  pub fn with_path(self, path: Option<&Path>) -> Self
+  {
+    Location{path: path.map(Path::to_owned), ..self}
+  }
+}
+
+impl fmt::Display for Location
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    match &self.path
+    {
+      Some(path) => write!(f, "{}:{}", path.display(), self.line),
+      None => write!(f, "line {}", self.line),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  #[test]
+  fn test_display()
+  {
+    assert_eq!(Location::new(Some(Path::new("src/foo.rs")), 42, 3).to_string(), "src/foo.rs:42");
+    assert_eq!(Location::new(None, 42, 3).to_string(), "line 42");
+  }
+}
+
+use std::fmt;
+use std::path::{Path, PathBuf};
@@ -1,33 +1,74 @@
 use super::*;
 
 mod section;
-pub use section::{Section, Marker, Section_List};
+pub use section::{Section, Section_Parts, Marker, Section_List};
+
+mod arguments;
+pub use arguments::{Argument, Arguments};
+
+mod marker_style;
+pub use marker_style::{Marker_Style, Inline_Line};
+
+mod user_region;
+pub use user_region::{User_Region, User_Region_Error, find_user_regions, strip_user_regions, restore_user_regions};
 
 mod parser;
-pub use parser::parse as find;
+pub use parser::{parse as find, parse_nested as find_nested, find_marker_line, escape_markers, unescape_markers};
+pub(crate) use parser::{end_marker_name, parse_sections as find_sections};
 
 pub type Result<T=(), E=Parse_Error> = std::result::Result<T, E>;
 
-#[derive(Clone, Debug, Error)]
+/// The locations point at the offending marker lines
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
 pub enum Parse_Error
 {
-  #[error("syntax error: {0}")]
-  SYNTAX(#[from] parser::Syntax_Error),
-  #[error("invalid blake3 checksum: {0}")]
-  INVALID_CHECKSUM(#[from] blake3::HexError),
+  #[error("{location}: section `{identifier}` has no end marker")]
+  UNTERMINATED_SECTION{location: Location, identifier: String},
+  #[error("{location}: end marker without a begin marker")]
+  ORPHAN_END_MARKER{location: Location},
+  #[error("{location}: begin marker of section `{identifier}` inside of section `{outer_identifier}` ({outer_location}). Is the end marker of `{outer_identifier}` missing?")]
+  NESTED_BEGIN_MARKER{location: Location, identifier: String, outer_location: Box<Location>, outer_identifier: String},
+  #[error("{location}: end marker named `{name}` closes section `{identifier}` ({begin_location})")]
+  MISMATCHED_END_MARKER{location: Location, name: String, begin_location: Box<Location>, identifier: String},
+  /// Only one inline section per line is supported
+  #[error("{location}: a second section starts on the line of the inline section `{identifier}`")]
+  MULTIPLE_INLINE_SECTIONS{location: Location, identifier: String},
+  /// E.g. an argument with an unterminated quote
+  #[error("{location}: begin marker of section `{identifier}` has a malformed argument list")]
+  MALFORMED_ARGUMENTS{location: Location, identifier: String},
+  #[error("{location}: unknown checksum algorithm `{tag}`")]
+  UNKNOWN_CHECKSUM_ALGORITHM{location: Location, tag: String},
 }
 
-impl PartialEq for Parse_Error
+impl Parse_Error
 {
-  fn eq(&self, other: &Self) -> bool
+  /// Sets the file the error was found in
+  pub fn with_path(self, path: Option<&Path>) -> Self
+  {
+    self.map_locations(|location| location.with_path(path))
+  }
+
+  /// Moves the locations by `offset` lines, for errors found in a part of a file
+  pub(crate) fn with_line_offset(self, offset: usize) -> Self
+  {
+    self.map_locations(|location| Location{line: location.line + offset, ..location})
+  }
+
+  fn map_locations(self, f: impl Fn(Location) -> Location) -> Self
   {
     use Parse_Error::*;
-    match (self, other)
+    match self
     {
-      (SYNTAX(a), SYNTAX(b)) => a == b,
-      (INVALID_CHECKSUM(a), INVALID_CHECKSUM(b)) => format!("{a}") == format!("{b}"),
-      (SYNTAX(_), _) | (INVALID_CHECKSUM(_), _) => false,
+      UNTERMINATED_SECTION{location, identifier} => UNTERMINATED_SECTION{location: f(location), identifier},
+      ORPHAN_END_MARKER{location} => ORPHAN_END_MARKER{location: f(location)},
+      NESTED_BEGIN_MARKER{location, identifier, outer_location, outer_identifier} => NESTED_BEGIN_MARKER{location: f(location), identifier, outer_location: Box::new(f(*outer_location)), outer_identifier},
+      MISMATCHED_END_MARKER{location, name, begin_location, identifier} => MISMATCHED_END_MARKER{location: f(location), name, begin_location: Box::new(f(*begin_location)), identifier},
+      MULTIPLE_INLINE_SECTIONS{location, identifier} => MULTIPLE_INLINE_SECTIONS{location: f(location), identifier},
+      MALFORMED_ARGUMENTS{location, identifier} => MALFORMED_ARGUMENTS{location: f(location), identifier},
+      UNKNOWN_CHECKSUM_ALGORITHM{location, tag} => UNKNOWN_CHECKSUM_ALGORITHM{location: f(location), tag},
     }
   }
 }
-impl Eq for Parse_Error {}
\ No newline at end of file
+
+use std::path::Path;
+use location::Location;
@@ -0,0 +1,189 @@
+/// The arguments of a begin marker.
+///
+/// Both `<< codegen table(users, columns="id,name") >>` and
+/// `<< codegen table users columns=id,name >>` pass the positional argument
+/// `users` and the named argument `columns` with the value `id,name`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Arguments<'a>
+{
+  /// The arguments as written in the marker (including the parentheses)
+  pub source: &'a str,
+  pub list: SmallVec<[Argument<'a>; 4]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argument<'a>
+{
+  pub name: Option<&'a str>,
+  /// The value without surrounding quotes
+  pub value: &'a str,
+}
+
+impl<'a> Arguments<'a>
+{
+  pub fn is_empty(&self) -> bool
+  {
+    self.list.is_empty()
+  }
+
+  /// The value of the named argument `name`
+  pub fn get(&self, name: &str) -> Option<&'a str>
+  {
+    self.list.iter().find(|a| a.name == Some(name)).map(|a| a.value)
+  }
+
+  /// The values of all unnamed arguments
+  pub fn positional(&self) -> impl Iterator<Item=&'a str> + '_
+  {
+    self.list.iter().filter(|a| a.name.is_none()).map(|a| a.value)
+  }
+}
+
+/// Parses `(a, b="c")` at the beginning of `text`, returning the arguments and
+/// the remaining text.
+pub(crate) fn parse_parenthesized(text: &str) -> Option<(Arguments<'_>, &str)>
+{
+  let mut rest = text.strip_prefix('(')?.trim_start_matches(' ');
+  let mut list = smallvec![];
+
+  while !rest.starts_with(')')
+  {
+    let (argument, r) = parse_argument(rest, true, |r| r.starts_with([',', ')', ' ']))?;
+    list.push(argument);
+
+    rest = r.trim_start_matches(' ');
+    rest = match rest.strip_prefix(',')
+    {
+      Some(r) => r.trim_start_matches(' '),
+      None if rest.starts_with(')') => rest,
+      None => return None,
+    };
+  }
+
+  let rest = &rest[1..];
+  Some((Arguments{source: &text[..text.len()-rest.len()], list}, rest))
+}
+
+/// Parses ` a b=c d="e f"` at the beginning of `text` until reaching
+/// `terminator`, returning the arguments and the remaining text.
+pub(crate) fn parse_space_separated<'a>(text: &'a str, terminator: &str) -> Option<(Arguments<'a>, &'a str)>
+{
+  debug_assert!(!terminator.is_empty());
+
+  let mut rest = text;
+  let mut list = smallvec![];
+
+  loop
+  {
+    let r = rest.trim_start_matches(' ');
+    if r.starts_with(terminator) || r.is_empty()
+    {
+      break;
+    }
+    if r.len() == rest.len()
+    {
+      return None;
+    }
+
+    let (argument, r) = parse_argument(r, false, |r| r.starts_with(' ') || r.starts_with(terminator))?;
+    list.push(argument);
+    rest = r;
+  }
+
+  let source = text[..text.len()-rest.len()].trim_start_matches(' ');
+  Some((Arguments{source, list}, rest))
+}
+
+fn parse_argument(text: &str, spaces_around_equals: bool, is_end: impl Fn(&str) -> bool) -> Option<(Argument<'_>, &str)>
+{
+  let (name, rest) = match parse_name(text, spaces_around_equals)
+  {
+    Some((name, rest)) => (Some(name), rest),
+    None => (None, text),
+  };
+
+  let (value, rest) = match rest.strip_prefix('"')
+  {
+    Some(quoted) =>
+    {
+      let len = quoted.find('"')?;
+      (&quoted[..len], &quoted[len+1..])
+    }
+    None =>
+    {
+      let len = rest.char_indices().map(|(i, _)| i).find(|&i| is_end(&rest[i..]) || rest[i..].starts_with('"')).unwrap_or(rest.len());
+      if len == 0
+      {
+        return None;
+      }
+      (&rest[..len], &rest[len..])
+    }
+  };
+
+  if !rest.is_empty() && !is_end(rest)
+  {
+    return None;
+  }
+
+  Some((Argument{name, value}, rest))
+}
+
+fn parse_name(text: &str, spaces_around_equals: bool) -> Option<(&str, &str)>
+{
+  let len = text.bytes().take_while(|&c| c == b'_' || c.is_ascii_alphanumeric()).count();
+  if len == 0
+  {
+    return None;
+  }
+
+  let (name, rest) = text.split_at(len);
+  let rest = match spaces_around_equals
+  {
+    true => rest.trim_start_matches(' ').strip_prefix('=')?.trim_start_matches(' '),
+    false => rest.strip_prefix('=')?,
+  };
+  Some((name, rest))
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  #[test]
+  fn test_parenthesized()
+  {
+    let (args, rest) = parse_parenthesized("(users, columns=\"id,name\") >>").unwrap();
+    assert_eq!(rest, " >>");
+    assert_eq!(args.source, "(users, columns=\"id,name\")");
+    assert_eq!(args.list.as_slice(), &[Argument{name: None, value: "users"}, Argument{name: Some("columns"), value: "id,name"}]);
+
+    assert_eq!(parse_parenthesized("()").unwrap().0.list.as_slice(), &[]);
+    assert_eq!(parse_parenthesized("( a , b = c, )").unwrap().0.list.as_slice(), &[Argument{name: None, value: "a"}, Argument{name: Some("b"), value: "c"}]);
+    assert_eq!(parse_parenthesized("(\"a b\")").unwrap().0.list.as_slice(), &[Argument{name: None, value: "a b"}]);
+    assert_eq!(parse_parenthesized("(x=\"\")").unwrap().0.list.as_slice(), &[Argument{name: Some("x"), value: ""}]);
+
+    assert_eq!(parse_parenthesized("(a"), None);
+    assert_eq!(parse_parenthesized("(a b)"), None);
+    assert_eq!(parse_parenthesized("(\"a)"), None);
+    assert_eq!(parse_parenthesized("(,)"), None);
+  }
+
+  #[test]
+  fn test_space_separated()
+  {
+    let (args, rest) = parse_space_separated(" users columns=id,name >> x", ">>").unwrap();
+    assert_eq!(rest, " >> x");
+    assert_eq!(args.source, "users columns=id,name");
+    assert_eq!(args.list.as_slice(), &[Argument{name: None, value: "users"}, Argument{name: Some("columns"), value: "id,name"}]);
+    assert_eq!(args.get("columns"), Some("id,name"));
+    assert_eq!(args.positional().collect::<Vec<_>>(), vec!["users"]);
+
+    assert_eq!(parse_space_separated(" a=\"b c\">>", ">>").unwrap().0.list.as_slice(), &[Argument{name: Some("a"), value: "b c"}]);
+    assert_eq!(parse_space_separated(" >>", ">>").unwrap().0, Arguments::default());
+    assert_eq!(parse_space_separated("a >>", ">>"), None);
+    assert_eq!(parse_space_separated(" \"a\"b >>", ">>"), None);
+  }
+}
+
+use super::*;
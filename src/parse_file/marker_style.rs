@@ -0,0 +1,284 @@
+/// The tokens forming the marker lines.
+///
+/// A begin marker consists of `open`, `begin_keyword`, the identifier and
+/// `close`. An end marker consists of `open`, `end_keyword`, the optional
+/// identifier, the optional checksum and `close`.
+///
+/// User regions inside of a section are marked the same way, with
+/// `keep_keyword` and a name and `end_keep_keyword`.
+///
+/// Spaces are allowed between all of these parts. They are required after a
+/// keyword ending with an alphanumeric character.
+///
+/// The tokens are borrowed, so a style can be loaded at runtime, e.g. from a
+/// configuration file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Marker_Style<'a>
+{
+  pub open: &'a str,
+  /// May be empty. The marker then ends after the identifier or checksum.
+  pub close: &'a str,
+  pub begin_keyword: &'a str,
+  pub end_keyword: &'a str,
+  pub keep_keyword: &'a str,
+  pub end_keep_keyword: &'a str,
+  /// Written between the delimiters and the content of a marker
+  pub padding: &'a str,
+  /// The opening delimiter is an operator, like `<<` in C++ or Haskell. Only
+  /// its first occurrence on a line may start a marker then, so
+  /// `std::cout << x; // << codegen foo >>` is handwritten code.
+  pub open_is_operator: bool,
+}
+
+impl Marker_Style<'static>
+{
+  /// `<< codegen foo >>` and `<< /codegen >>`, `<< keep bar >>` and
+  /// `<< /keep >>`
+  pub const DEFAULT : Self = Marker_Style{open: "<<", close: ">>", begin_keyword: "codegen", end_keyword: "/codegen", keep_keyword: "keep", end_keep_keyword: "/keep", padding: " ", open_is_operator: true};
+  /// `[[[ codegen foo ]]]` and `[[[ /codegen ]]]` for languages using `<<` as
+  /// an operator.
+  pub const BRACKETS : Self = Marker_Style{open: "[[[", close: "]]]", begin_keyword: "codegen", end_keyword: "/codegen", keep_keyword: "keep", end_keep_keyword: "/keep", padding: " ", open_is_operator: false};
+  /// `#region gen:foo` and `#endregion gen`, `#region keep:bar` and
+  /// `#endregion keep`. Plain regions like `#region Helpers` and `#endregion`
+  /// are handwritten code.
+  pub const REGION : Self = Marker_Style{open: "#", close: "", begin_keyword: "region gen:", end_keyword: "endregion gen", keep_keyword: "region keep:", end_keep_keyword: "endregion keep", padding: "", open_is_operator: false};
+}
+
+impl Marker_Style<'_>
+{
+  pub fn is_valid(&self) -> bool
+  {
+    !self.open.is_empty()
+    && !self.begin_keyword.is_empty()
+    && !self.end_keyword.is_empty()
+    && !self.keep_keyword.is_empty()
+    && !self.end_keep_keyword.is_empty()
+    && self.begin_keyword != self.end_keyword
+    && self.keep_keyword != self.end_keep_keyword
+    && self.keep_keyword != self.begin_keyword
+    && self.padding.chars().all(|c| c == ' ')
+  }
+
+  pub fn write_begin_line<W: fmt::Write>(&self, out: &mut W, marker: &Marker, identifier: &str, arguments: &Arguments) -> fmt::Result
+  {
+    let separator = separator_after(self.begin_keyword);
+    write!(out, "{i}{before}{open}{pad}{keyword}{separator}{identifier}", i=marker.indentation, before=marker.before_marker, open=self.open, pad=self.padding, keyword=self.begin_keyword)?;
+    match arguments.source.starts_with('(')
+    {
+      true => out.write_str(arguments.source)?,
+      false if !arguments.source.is_empty() => write!(out, " {}", arguments.source)?,
+      false => (),
+    }
+    self.write_close(out, marker.after_marker)
+  }
+
+  /// `name` is written only if not empty and if the style has a closing
+  /// delimiter, as it couldn't be told apart from the text after the marker
+  /// otherwise.
+  pub fn write_end_line<W: fmt::Write>(&self, out: &mut W, marker: &Marker, name: &str, checksum: &str) -> fmt::Result
+  {
+    write!(out, "{i}{before}{open}{pad}{keyword}", i=marker.indentation, before=marker.before_marker, open=self.open, pad=self.padding, keyword=self.end_keyword)?;
+    if !name.is_empty() && !self.close.is_empty()
+    {
+      write!(out, " {name}")?;
+    }
+    if !checksum.is_empty()
+    {
+      write!(out, " {checksum}")?;
+    }
+    self.write_close(out, marker.after_marker)
+  }
+
+  /// Writes the begin marker line exactly as it was parsed
+  pub fn copy_begin_line<W: fmt::Write>(&self, out: &mut W, marker: &Marker) -> fmt::Result
+  {
+    write!(out, "{i}{before}{source}{after}", i=marker.indentation, before=marker.before_marker, source=marker.source, after=marker.after_marker)
+  }
+
+  /// Writes the end marker line exactly as it was parsed, except for the
+  /// checksum, which is replaced by `checksum`. `identifier` is the one of the
+  /// section, so a name consisting of hex digits is kept.
+  pub fn copy_end_line<W: fmt::Write>(&self, out: &mut W, marker: &Marker, identifier: &str, checksum: &str) -> fmt::Result
+  {
+    let span = checksum_span(marker.source, identifier, self).expect("the marker was parsed with this style");
+    let (before_checksum, after_checksum) = (&marker.source[..span.start], &marker.source[span.end..]);
+    write!(out, "{i}{before}", i=marker.indentation, before=marker.before_marker)?;
+    match (span.is_empty(), checksum.is_empty())
+    {
+      (true, false) => write!(out, "{before_checksum} {checksum}")?,
+      (false, false) => write!(out, "{before_checksum}{checksum}")?,
+      // Removes the separator of the removed checksum, too
+      (false, true) => out.write_str(before_checksum.trim_end_matches(' '))?,
+      (true, true) => out.write_str(before_checksum)?,
+    }
+    write!(out, "{after_checksum}{after}", after=marker.after_marker)
+  }
+
+  /// Writes the line of an inline section with its code between the markers
+  pub fn write_inline_line<W: fmt::Write>(&self, out: &mut W, line: &Inline_Line) -> fmt::Result
+  {
+    let Inline_Line{begin, identifier, arguments, code, end, end_name, checksum} = *line;
+    // Passing the code as the text after the begin marker separates both, if there is no closing delimiter
+    let after_begin = format!("{}{code}", begin.after_marker);
+    self.write_begin_line(out, &Marker{after_marker: &after_begin, ..*begin}, identifier, arguments)?;
+    self.write_end_line(out, &Marker{indentation: Indentation(""), ..*end}, end_name, checksum)
+  }
+
+  /// Writes the line of an inline section exactly as it was parsed, except for
+  /// the code and the checksum
+  pub fn copy_inline_line<W: fmt::Write>(&self, out: &mut W, begin: &Marker, identifier: &str, code: &str, end: &Marker, checksum: &str) -> fmt::Result
+  {
+    self.copy_begin_line(out, begin)?;
+    let glued = self.close.is_empty() && begin.after_marker.is_empty() && starts_with_word(code);
+    if glued
+    {
+      out.write_char(' ')?;
+    }
+    out.write_str(code)?;
+    self.copy_end_line(out, end, identifier, checksum)
+  }
+
+  fn write_close<W: fmt::Write>(&self, out: &mut W, after_marker: &str) -> fmt::Result
+  {
+    match self.close.is_empty()
+    {
+      // Parsed markers keep the spaces before the text after them
+      true if starts_with_word(after_marker) => write!(out, " {after_marker}"),
+      true => out.write_str(after_marker),
+      false => write!(out, "{pad}{close}{after_marker}", pad=self.padding, close=self.close),
+    }
+  }
+}
+
+/// The parts of the line of an inline section written by
+/// [`Marker_Style::write_inline_line`]
+#[derive(Clone, Copy, Debug)]
+pub struct Inline_Line<'a, 'b>
+{
+  pub begin: &'a Marker<'a>,
+  pub identifier: &'a str,
+  pub arguments: &'a Arguments<'b>,
+  pub code: &'a str,
+  pub end: &'a Marker<'a>,
+  /// Written into the end marker, see [`Marker_Style::write_end_line`]
+  pub end_name: &'a str,
+  pub checksum: &'a str,
+}
+
+impl Default for Marker_Style<'_>
+{
+  fn default() -> Self
+  {
+    Marker_Style::DEFAULT
+  }
+}
+
+/// Whether spaces are required between the keyword and the following token
+pub(crate) fn requires_separator(keyword: &str) -> bool
+{
+  keyword.chars().last().map_or(false, |c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn separator_after(keyword: &str) -> &'static str
+{
+  match requires_separator(keyword)
+  {
+    true => " ",
+    false => "",
+  }
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  #[test]
+  fn test_write()
+  {
+    let marker = Marker{indentation: Indentation("  "), before_marker: "// ", source: "", after_marker: " xyz"};
+
+    let begin = |style: Marker_Style| {let mut x = String::new(); style.write_begin_line(&mut x, &marker, "foo", &Arguments::default()).unwrap(); x};
+    let end = |style: Marker_Style, checksum| {let mut x = String::new(); style.write_end_line(&mut x, &marker, "", checksum).unwrap(); x};
+    let named_end = |style: Marker_Style, checksum| {let mut x = String::new(); style.write_end_line(&mut x, &marker, "foo", checksum).unwrap(); x};
+    let end_with = |style: Marker_Style, after_marker| {let mut x = String::new(); style.write_end_line(&mut x, &Marker{indentation: Indentation(""), before_marker: "", source: "", after_marker}, "", "").unwrap(); x};
+
+    assert_eq!(begin(Marker_Style::DEFAULT), "  // << codegen foo >> xyz");
+    assert_eq!(end(Marker_Style::DEFAULT, ""), "  // << /codegen >> xyz");
+    assert_eq!(end(Marker_Style::DEFAULT, "af13"), "  // << /codegen af13 >> xyz");
+    assert_eq!(begin(Marker_Style::BRACKETS), "  // [[[ codegen foo ]]] xyz");
+    assert_eq!(begin(Marker_Style::REGION), "  // #region gen:foo xyz");
+    assert_eq!(end(Marker_Style::REGION, "af13"), "  // #endregion gen af13 xyz");
+    assert_eq!(named_end(Marker_Style::DEFAULT, ""), "  // << /codegen foo >> xyz");
+    assert_eq!(named_end(Marker_Style::DEFAULT, "af13"), "  // << /codegen foo af13 >> xyz");
+    assert_eq!(named_end(Marker_Style::REGION, "af13"), "  // #endregion gen af13 xyz");
+    assert_eq!(end_with(Marker_Style::REGION, ";"), "#endregion gen;");
+    assert_eq!(end_with(Marker_Style::REGION, "xyz"), "#endregion gen xyz");
+
+    let with_arguments = |source| {let mut x = String::new(); Marker_Style::DEFAULT.write_begin_line(&mut x, &marker, "foo", &Arguments{source, list: smallvec![]}).unwrap(); x};
+    assert_eq!(with_arguments("(a, b=c)"), "  // << codegen foo(a, b=c) >> xyz");
+    assert_eq!(with_arguments("a b=c"), "  // << codegen foo a b=c >> xyz");
+  }
+
+  #[test]
+  fn test_copy()
+  {
+    let marker = |source| Marker{indentation: Indentation("\t"), before_marker: "/* ", source, after_marker: " */"};
+    let end = |style: Marker_Style, source, checksum| {let mut x = String::new(); style.copy_end_line(&mut x, &marker(source), "foo", checksum).unwrap(); x};
+
+    let mut begin = String::new();
+    Marker_Style::DEFAULT.copy_begin_line(&mut begin, &marker("<<codegen   foo>>")).unwrap();
+    assert_eq!(begin, "\t/* <<codegen   foo>> */");
+
+    assert_eq!(end(Marker_Style::DEFAULT, "<<  /codegen   ab12  >>", "cd34"), "\t/* <<  /codegen   cd34  >> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<<  /codegen   ab12  >>", "sha256:cd34"), "\t/* <<  /codegen   sha256:cd34  >> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<<  /codegen   ab12  >>", ""), "\t/* <<  /codegen  >> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<</codegen>>", "cd34"), "\t/* <</codegen cd34>> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<</codegen>>", ""), "\t/* <</codegen>> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<< /codegen foo >>", "cd34"), "\t/* << /codegen foo cd34 >> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<< /codegen foo ab12 >>", ""), "\t/* << /codegen foo >> */");
+    assert_eq!(end(Marker_Style::DEFAULT, "<< /codegen beef >>", "blake3:cd34"), "\t/* << /codegen blake3:cd34 >> */");
+    let mut named = String::new();
+    Marker_Style::DEFAULT.copy_end_line(&mut named, &marker("<< /codegen beef >>"), "beef", "blake3:cd34").unwrap();
+    assert_eq!(named, "\t/* << /codegen beef blake3:cd34 >> */");
+    assert_eq!(end(Marker_Style::REGION, "#endregion gen af13", ""), "\t/* #endregion gen */");
+  }
+
+  #[test]
+  fn test_inline()
+  {
+    let begin = |source, after_marker| Marker{indentation: Indentation("  "), before_marker: "x = {/*", source, after_marker};
+    let end = |source| Marker{indentation: Indentation(""), before_marker: "/*", source, after_marker: "*/};"};
+
+    let write = |style: Marker_Style, code, checksum| {let mut x = String::new(); style.write_inline_line(&mut x, &Inline_Line{begin: &begin("", "*/"), identifier: "foo", arguments: &Arguments::default(), code, end: &end(""), end_name: "", checksum}).unwrap(); x};
+    assert_eq!(write(Marker_Style::DEFAULT, "1,2", ""), "  x = {/*<< codegen foo >>*/1,2/*<< /codegen >>*/};");
+    assert_eq!(write(Marker_Style::DEFAULT, "1,2", "ab12"), "  x = {/*<< codegen foo >>*/1,2/*<< /codegen ab12 >>*/};");
+
+    let mut region = String::new();
+    Marker_Style::REGION.write_inline_line(&mut region, &Inline_Line{begin: &Marker{before_marker: "", ..begin("", "")}, identifier: "foo", arguments: &Arguments::default(), code: "1,2", end: &Marker{before_marker: "", after_marker: "", ..end("")}, end_name: "", checksum: ""}).unwrap();
+    assert_eq!(region, "  #region gen:foo 1,2#endregion gen");
+    region.clear();
+    Marker_Style::REGION.write_inline_line(&mut region, &Inline_Line{begin: &Marker{before_marker: "", ..begin("", " ")}, identifier: "foo", arguments: &Arguments::default(), code: "1,2", end: &Marker{before_marker: " ", after_marker: "", ..end("")}, end_name: "", checksum: ""}).unwrap();
+    assert_eq!(region, "  #region gen:foo 1,2 #endregion gen");
+
+    let mut copied = String::new();
+    Marker_Style::DEFAULT.copy_inline_line(&mut copied, &begin("<<codegen foo>>", "*/"), "foo", "1,2", &end("<</codegen ab12>>"), "cd34").unwrap();
+    assert_eq!(copied, "  x = {/*<<codegen foo>>*/1,2/*<</codegen cd34>>*/};");
+  }
+
+  #[test]
+  fn test_valid()
+  {
+    assert!(Marker_Style::DEFAULT.is_valid());
+    assert!(Marker_Style::BRACKETS.is_valid());
+    assert!(Marker_Style::REGION.is_valid());
+    assert!(!Marker_Style{open: "", .. Marker_Style::DEFAULT}.is_valid());
+    assert!(!Marker_Style{end_keyword: "codegen", .. Marker_Style::DEFAULT}.is_valid());
+    assert!(!Marker_Style{keep_keyword: "codegen", .. Marker_Style::DEFAULT}.is_valid());
+  }
+}
+
+use super::{Marker, Arguments};
+use crate::indentation::Indentation;
+use super::parser::{checksum_span, starts_with_word};
+use std::fmt;
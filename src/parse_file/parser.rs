@@ -1,55 +1,309 @@
 use super::*;
 
 mod line;
+pub(crate) use line::end_marker_name;
+pub(super) use line::{checksum_span, starts_with_word, parse as parse_line, parse_keep, Line, Keep_Line};
 
-#[derive(Parser)]
-#[grammar = "parse_file/section_grammar.pest"]
-pub struct Section_Parser
+fn marker_location(line: usize, marker: &Marker) -> Location
 {
+  Location::new(None, line, marker.column())
 }
 
-pub type Syntax_Error = crate::pest::error::Error<Rule>;
+pub fn parse<'a>(code: &'a str, style: &Marker_Style) -> Result<Section_List<'a>>
+{
+  parse_sections(code, style, false, false)
+}
+
+/// Like [`parse`], but allows sections inside of sections. These are listed in
+/// the `children` of their enclosing section.
+pub fn parse_nested<'a>(code: &'a str, style: &Marker_Style) -> Result<Section_List<'a>>
+{
+  parse_sections(code, style, true, false)
+}
 
-pub fn parse(code: &str) -> Result<Section_List>
+/// Like [`parse`] or, with `allow_nesting`, [`parse_nested`].
+///
+/// With `named_end_markers`, a single untagged token in an end marker is its
+/// name, even if it could be a checksum. Checksums then follow the name or are
+/// tagged, like `<< /codegen foo ab12 >>` or `<< /codegen blake3:ab12 >>`.
+pub(crate) fn parse_sections<'a>(code: &'a str, style: &Marker_Style, allow_nesting: bool, named_end_markers: bool) -> Result<Section_List<'a>>
 {
   let mut sections = smallvec![];
 
-  let result = Section_Parser::parse(Rule::file, code)?;
-  for r in result
+  // The start of the handwritten code at the top level
+  let mut start = 0;
+  // The sections whose end marker wasn't found yet, the innermost last
+  let mut open : Vec<Open_Section> = Vec::new();
+  let mut line_start = 0;
+  let mut line_number = 0;
+  while line_start < code.len()
   {
-    match r.as_rule()
+    line_number += 1;
+    let (line, next_line_start) = match code[line_start..].find('\n')
     {
-      Rule::section => sections.push(parse_section(r)?),
-      Rule::EOI => (),
-      _ => unimplemented!("{:?}", r.as_rule()),
-    }
+      Some(len) =>
+      {
+        let line = &code[line_start..line_start+len];
+        (line.strip_suffix('\r').unwrap_or(line), line_start+len+1)
+      }
+      None => (&code[line_start..], code.len()),
+    };
+
+    match line::parse(line, style)
+    {
+      Line::CODE(_) => (),
+      Line::MALFORMED_BEGIN_MARKER{marker, identifier} => return Err(Parse_Error::MALFORMED_ARGUMENTS{location: marker_location(line_number, &marker), identifier: identifier.to_owned()}),
+      Line::BEGIN_CODEGEN{marker, identifier, arguments} =>
+      {
+        match open.last_mut()
+        {
+          None if start < line_start => sections.push(Section::HANDWRITTEN(&code[start..line_start])),
+          None => (),
+          Some(outer) if allow_nesting => outer.push_handwritten(code, line_start),
+          Some(outer) => return Err(Parse_Error::NESTED_BEGIN_MARKER{
+            location: marker_location(line_number, &marker),
+            identifier: identifier.to_owned(),
+            outer_location: Box::new(marker_location(outer.line, &outer.begin)),
+            outer_identifier: outer.identifier.to_owned(),
+          }),
+        }
+        open.push(Open_Section{begin: marker, identifier, arguments, code_start: next_line_start, line: line_number, children: Vec::new(), handwritten_start: next_line_start});
+      }
+      Line::END_CODEGEN{marker: end, name, checksum} =>
+      {
+        let mut section = match open.pop()
+        {
+          Some(section) => section,
+          None => return Err(Parse_Error::ORPHAN_END_MARKER{location: marker_location(line_number, &end)}),
+        };
+        let checksum = end_marker_checksum(name, checksum, section.identifier, style, named_end_markers, marker_location(line_number, &end), marker_location(section.line, &section.begin))?;
+        let (checksum_algorithm, checksum) = parse_tagged_checksum(checksum, line_number, &end)?;
+        if !section.children.is_empty()
+        {
+          section.push_handwritten(code, line_start);
+        }
+        let Open_Section{begin, identifier, arguments, code_start, children, ..} = section;
+        let code = &code[code_start..line_start];
+        let section = Section::CODEGEN{identifier, arguments, code, checksum, checksum_algorithm, begin, end, children};
+        match open.last_mut()
+        {
+          Some(outer) =>
+          {
+            outer.children.push(section);
+            outer.handwritten_start = next_line_start;
+          }
+          None =>
+          {
+            sections.push(section);
+            start = next_line_start;
+          }
+        }
+      }
+      Line::INLINE_CODEGEN{begin, identifier, arguments, code: inline_code, end, end_name, checksum} =>
+      {
+        let end_location = Location::new(None, line_number, begin.to_string().chars().count() + inline_code.chars().count() + end.column());
+        let checksum = end_marker_checksum(end_name, checksum, identifier, style, named_end_markers, end_location, marker_location(line_number, &begin))?;
+        let (checksum_algorithm, checksum) = parse_tagged_checksum(checksum, line_number, &end)?;
+        // Only one inline section per line is supported, so a second one must not be taken for code
+        if let Line::BEGIN_CODEGEN{marker: second, ..} | Line::INLINE_CODEGEN{begin: second, ..} = line::parse(end.after_marker, style)
+        {
+          let column = line[..line.len()-end.after_marker.len()].chars().count() + second.column();
+          return Err(Parse_Error::MULTIPLE_INLINE_SECTIONS{location: Location::new(None, line_number, column), identifier: identifier.to_owned()});
+        }
+        let section = Section::INLINE{identifier, arguments, code: inline_code, checksum, checksum_algorithm, begin, end};
+        match open.last_mut()
+        {
+          None =>
+          {
+            if start < line_start
+            {
+              sections.push(Section::HANDWRITTEN(&code[start..line_start]));
+            }
+            sections.push(section);
+            start = next_line_start;
+          }
+          Some(outer) if allow_nesting =>
+          {
+            outer.push_handwritten(code, line_start);
+            outer.children.push(section);
+            outer.handwritten_start = next_line_start;
+          }
+          Some(outer) => return Err(Parse_Error::NESTED_BEGIN_MARKER{
+            location: marker_location(line_number, &begin),
+            identifier: identifier.to_owned(),
+            outer_location: Box::new(marker_location(outer.line, &outer.begin)),
+            outer_identifier: outer.identifier.to_owned(),
+          }),
+        }
+      }
+    };
+
+    line_start = next_line_start;
+  }
+
+  match open.pop()
+  {
+    None if start < code.len() => sections.push(Section::HANDWRITTEN(&code[start..])),
+    None => (),
+    Some(Open_Section{line, begin, identifier, ..}) => return Err(Parse_Error::UNTERMINATED_SECTION{location: marker_location(line, &begin), identifier: identifier.to_owned()}),
   }
 
   Ok(sections)
 }
 
-fn parse_section(node: crate::pest::iterators::Pair<Rule>) -> Result<Section>
+/// Returns the 1-based line number and the text of the first marker line in `code`
+pub fn find_marker_line<'a>(code: &'a str, style: &Marker_Style) -> Option<(usize, &'a str)>
 {
-  debug_assert_eq!(node.as_rule(), Rule::section);
-  
-  let node = node.into_inner().next().unwrap();
-  let s = match node.as_rule()
-  {
-    Rule::code => Section::HANDWRITTEN(node.as_str()),
-    Rule::generated => {
-      let mut xs = node.into_inner();
-      let (begin, identifier) = line::parse_begin_marker(xs.next().unwrap());
-      let code = xs.next().unwrap().as_str();
-      let (end, checksum) = line::parse_end_marker(xs.next().unwrap());
-    
-      let checksum = parse_checksum(checksum);
-
-      Section::CODEGEN { identifier, code, checksum, begin, end }
+  code.split('\n')
+    .map(|line| line.strip_suffix('\r').unwrap_or(line))
+    .enumerate()
+    .find(|(_, line)| open_delimiter_end(line, style).is_some())
+    .map(|(i, line)| (i+1, line))
+}
+
+/// Inserts a `\\` after the opening delimiter of each marker in `code`, so the
+/// parser treats these lines as regular code.
+///
+/// Markers which are escaped already get another `\\`, so
+/// [`unescape_markers`] restores `code` exactly.
+pub fn escape_markers(code: &str, style: &Marker_Style) -> String
+{
+  map_lines(code, |line|
+  {
+    let mut line = line.to_owned();
+    // From right to left, so each delimiter sees the following ones escaped, as in `unescape_markers`
+    for end in delimiter_ends(&line.clone(), style).into_iter().rev()
+    {
+      if starts_marker(&line, end, style)
+      {
+        line.insert(end, '\\');
+      }
     }
-    _ => unreachable!(),
-  };
+    line
+  })
+}
 
-  Ok(s)
+/// Reverts [`escape_markers`], removing one `\\` after the opening delimiter
+/// of each escaped marker.
+pub fn unescape_markers(code: &str, style: &Marker_Style) -> String
+{
+  map_lines(code, |line|
+  {
+    let mut line = line.to_owned();
+    let mut removed = 0;
+    for end in delimiter_ends(&line.clone(), style)
+    {
+      let end = end - removed;
+      if line[end..].starts_with('\\') && starts_marker(&line, end, style)
+      {
+        line.remove(end);
+        removed += 1;
+      }
+    }
+    line
+  })
+}
+
+fn map_lines(code: &str, mut f: impl FnMut(&str) -> String) -> String
+{
+  let mut mapped = String::with_capacity(code.len());
+  for (i, line) in code.split('\n').enumerate()
+  {
+    if i > 0
+    {
+      mapped.push('\n');
+    }
+    mapped += f(line).as_str();
+  }
+  mapped
+}
+
+/// The end of each (possibly overlapping) opening delimiter in `line`
+fn delimiter_ends(line: &str, style: &Marker_Style) -> Vec<usize>
+{
+  line.char_indices()
+    .filter(|&(pos, _)| line[pos..].starts_with(style.open))
+    .map(|(pos, _)| pos + style.open.len())
+    .collect()
+}
+
+/// Whether the opening delimiter ending at `end` starts a marker, ignoring
+/// the `\\`s escaping it
+fn starts_marker(line: &str, end: usize, style: &Marker_Style) -> bool
+{
+  let unescaped = format!("{}{}", style.open, line[end..].trim_start_matches('\\'));
+  let parsed = line::parse(&unescaped, style);
+  match parsed
+  {
+    Line::CODE(_) => false,
+    Line::BEGIN_CODEGEN{marker, ..} | Line::END_CODEGEN{marker, ..} | Line::INLINE_CODEGEN{begin: marker, ..} | Line::MALFORMED_BEGIN_MARKER{marker, ..} => marker.before_marker.is_empty(),
+  }
+}
+
+fn open_delimiter_end(line: &str, style: &Marker_Style) -> Option<usize>
+{
+  match line::parse(line, style)
+  {
+    Line::CODE(_) => None,
+    Line::BEGIN_CODEGEN{marker, ..} | Line::END_CODEGEN{marker, ..} | Line::INLINE_CODEGEN{begin: marker, ..} | Line::MALFORMED_BEGIN_MARKER{marker, ..} => Some(marker.indentation.0.len() + marker.before_marker.len() + style.open.len()),
+  }
+}
+
+/// A section whose end marker wasn't found yet
+struct Open_Section<'a>
+{
+  begin: Marker<'a>,
+  identifier: &'a str,
+  arguments: Arguments<'a>,
+  code_start: usize,
+  /// The line of the begin marker
+  line: usize,
+  children: Vec<Section<'a>>,
+  /// The start of the handwritten code after the last nested section
+  handwritten_start: usize,
+}
+
+impl<'a> Open_Section<'a>
+{
+  fn push_handwritten(&mut self, code: &'a str, end: usize)
+  {
+    if self.handwritten_start < end
+    {
+      self.children.push(Section::HANDWRITTEN(&code[self.handwritten_start..end]));
+    }
+  }
+}
+
+/// Verifies the name of an end marker and returns its checksum.
+///
+/// A single hex token like `<< /codegen beef >>` is parsed as checksum, but is
+/// the name, if it equals the identifier of the section and the style has a
+/// closing delimiter. With `named_end_markers`, it's the name unless tagged.
+fn end_marker_checksum<'a>(name: &str, checksum: &'a str, identifier: &str, style: &Marker_Style, named_end_markers: bool, location: Location, begin_location: Location) -> Result<&'a str>
+{
+  match name
+  {
+    "" if checksum == identifier && !style.close.is_empty() => Ok(""),
+    "" if named_end_markers && !style.close.is_empty() && !checksum.is_empty() && !checksum.contains(':') => Err(Parse_Error::MISMATCHED_END_MARKER{location, name: checksum.to_owned(), begin_location: Box::new(begin_location), identifier: identifier.to_owned()}),
+    "" => Ok(checksum),
+    name if name == identifier => Ok(checksum),
+    name => Err(Parse_Error::MISMATCHED_END_MARKER{location, name: name.to_owned(), begin_location: Box::new(begin_location), identifier: identifier.to_owned()}),
+  }
+}
+
+/// Splits `tag:checksum` into the algorithm and the checksum bytes
+fn parse_tagged_checksum(checksum: &str, line_number: usize, end: &Marker) -> Result<(Option<Checksum_Algorithm>, ArrayVec<u8, 32>)>
+{
+  let (checksum_algorithm, checksum) = match checksum.split_once(':')
+  {
+    Some((tag, checksum)) => match Checksum_Algorithm::from_tag(tag)
+    {
+      Some(algorithm) => (Some(algorithm), checksum),
+      None => return Err(Parse_Error::UNKNOWN_CHECKSUM_ALGORITHM{location: marker_location(line_number, end), tag: tag.to_owned()}),
+    },
+    None => (None, checksum),
+  };
+  Ok((checksum_algorithm, parse_checksum(checksum)))
 }
 
 fn parse_checksum(checksum: &str) -> ArrayVec<u8, 32>
@@ -89,23 +343,14 @@ mod test
 {
   use super::*;
   use Section::*;
-  
-  #[test]
-  fn test_parse_section() -> Result
-  {
-    assert!(parse_section("").is_err());
-    assert_eq!(parse_section("xyz")?, HANDWRITTEN("xyz"));
-    assert_eq!(parse_section("x\ny\nz")?, HANDWRITTEN("x\ny\nz"));
-    assert_eq!(parse_section("x\ny\n")?, HANDWRITTEN("x\ny\n"));
 
-    Ok(())
-  }
-
-  fn parse_section(code: &str) -> Result<Section>
+  #[test]
+  fn test_handwritten()
   {
-    let mut result = Section_Parser::parse(Rule::section, code)?;
-  
-    super::parse_section(result.next().unwrap())
+    assert_eq!(find("xyz").unwrap_display(), smallvec![HANDWRITTEN("xyz")] as Section_List);
+    assert_eq!(find("x\ny\nz").unwrap_display(), smallvec![HANDWRITTEN("x\ny\nz")] as Section_List);
+    assert_eq!(find("x\ny\n").unwrap_display(), smallvec![HANDWRITTEN("x\ny\n")] as Section_List);
+    assert_eq!(find("x\r\ny\r\n").unwrap_display(), smallvec![HANDWRITTEN("x\r\ny\r\n")] as Section_List);
   }
   
   #[test]
@@ -117,18 +362,23 @@ mod test
     assert_eq!(find("// << codegen foo >>\n// << /codegen >>\n").unwrap_display(), smallvec![
       CODEGEN{
         identifier: "foo",
+        arguments: Arguments::default(),
         code: "",
         checksum: ArrayVec::new(),
+        checksum_algorithm: None,
         begin: Marker{
-          indentation: I(0),
+          indentation: I(""),
           before_marker: "// ",
+          source: "<< codegen foo >>",
           after_marker: "",
         },
         end: Marker{
-          indentation: I(0),
+          indentation: I(""),
           before_marker: "// ",
+          source: "<< /codegen >>",
           after_marker: "",
         },
+        children: vec![],
       },
     ] as Section_List);
   }
@@ -143,22 +393,229 @@ mod test
         HANDWRITTEN("x\ny\nz\n"),
         CODEGEN{
           identifier: "blub",
+          arguments: Arguments::default(),
           code: "  uvw\n",
           checksum: ArrayVec::new(),
+        checksum_algorithm: None,
           begin: Marker{
-            indentation: I(2),
+            indentation: I("  "),
             before_marker: "// ",
+            source: "<< codegen blub >>",
             after_marker: "",
           },
           end: Marker{
-            indentation: I(1),
+            indentation: I(" "),
             before_marker: "// ",
+            source: "<< /codegen >>",
             after_marker: "",
           },
+          children: vec![],
         },
         HANDWRITTEN("abc"),
       ] as Section_List);
   }
+
+  #[test]
+  fn test_crlf()
+  {
+    let code = "x\r\n<< codegen blub >>\r\nuvw\r\n<< /codegen >>\r\nabc\r\n";
+    let marker = |source| Marker{indentation: I(""), before_marker: "", source, after_marker: ""};
+    assert_eq!(
+      find(code).unwrap_display(),
+      smallvec![
+        HANDWRITTEN("x\r\n"),
+        CODEGEN{identifier: "blub", arguments: Arguments::default(), code: "uvw\r\n", checksum: ArrayVec::new(), checksum_algorithm: None, begin: marker("<< codegen blub >>"), end: marker("<< /codegen >>"), children: vec![]},
+        HANDWRITTEN("abc\r\n"),
+      ] as Section_List);
+  }
+
+  #[test]
+  fn test_style()
+  {
+    let code = "x\n#region gen:blub\nuvw\n#endregion gen af13\nabc";
+    let marker = |source| Marker{indentation: I(""), before_marker: "", source, after_marker: ""};
+    assert_eq!(
+      parse(code, &Marker_Style::REGION).unwrap_display(),
+      smallvec![
+        HANDWRITTEN("x\n"),
+        CODEGEN{identifier: "blub", arguments: Arguments::default(), code: "uvw\n", checksum: [0xaf, 0x13].into_iter().collect(), checksum_algorithm: None, begin: marker("#region gen:blub"), end: marker("#endregion gen af13"), children: vec![]},
+        HANDWRITTEN("abc"),
+      ] as Section_List);
+    assert_eq!(find(code).unwrap_display(), smallvec![HANDWRITTEN(code)] as Section_List);
+  }
+
+  #[test]
+  fn test_nested()
+  {
+    let code = "<< codegen outer >>\nx\n  << codegen inner >>\n  42\n  << /codegen ab12 >>\ny\n<< /codegen >>\n";
+    let sections = parse_nested(code, &Marker_Style::DEFAULT).unwrap_display();
+    let children = match &sections[..]
+    {
+      [CODEGEN{identifier: "outer", code: "x\n  << codegen inner >>\n  42\n  << /codegen ab12 >>\ny\n", children, ..}] => children,
+      sections => panic!("{sections:?}"),
+    };
+    match &children[..]
+    {
+      [HANDWRITTEN("x\n"), CODEGEN{identifier: "inner", code: "  42\n", checksum, children, ..}, HANDWRITTEN("y\n")] => {assert_eq!(checksum.as_slice(), &[0xab, 0x12]); assert!(children.is_empty());}
+      children => panic!("{children:?}"),
+    }
+
+    assert!(matches!(&find(code), Err(Parse_Error::NESTED_BEGIN_MARKER{..})));
+    assert_eq!(parse_nested("<< codegen a >>\n<< codegen b >>\n<< /codegen >>\n", &Marker_Style::DEFAULT), Err(Parse_Error::UNTERMINATED_SECTION{location: Location::new(None, 1, 1), identifier: "a".to_owned()}));
+    assert_eq!(parse_nested("<< codegen a >>\n<< /codegen >>\n<< /codegen >>\n", &Marker_Style::DEFAULT), Err(Parse_Error::ORPHAN_END_MARKER{location: Location::new(None, 3, 1)}));
+  }
+
+  #[test]
+  fn test_inline()
+  {
+    let code = "x\nint sizes[] = {/*<< codegen sizes >>*/1,2,3/*<< /codegen >>*/};\ny\n";
+    match &find(code).unwrap_display()[..]
+    {
+      [HANDWRITTEN("x\n"), INLINE{identifier: "sizes", code: "1,2,3", begin, end, ..}, HANDWRITTEN("y\n")] => assert_eq!(format!("{begin}1,2,3{end}"), "int sizes[] = {/*<< codegen sizes >>*/1,2,3/*<< /codegen >>*/};"),
+      sections => panic!("{sections:?}"),
+    }
+
+    let nested = "<< codegen outer >>\nx = /*<< codegen inner >>*/1/*<< /codegen >>*/;\n<< /codegen >>\n";
+    match &parse_nested(nested, &Marker_Style::DEFAULT).unwrap_display()[..]
+    {
+      [CODEGEN{children, ..}] => assert!(matches!(&children[..], [INLINE{identifier: "inner", code: "1", ..}])),
+      sections => panic!("{sections:?}"),
+    }
+    assert!(matches!(&find(nested), Err(Parse_Error::NESTED_BEGIN_MARKER{..})));
+
+    let two = "a /*<< codegen s >>*/1/*<< /codegen >>*/ b /*<< codegen t >>*/1/*<< /codegen >>*/\n";
+    assert_eq!(find(two), Err(Parse_Error::MULTIPLE_INLINE_SECTIONS{location: Location::new(None, 1, 46), identifier: "s".to_owned()}));
+    assert_eq!(find(two).unwrap_err().to_string(), "line 1: a second section starts on the line of the inline section `s`");
+  }
+
+  #[test]
+  fn test_named_end_markers()
+  {
+    let checksum = |code| match &find(code).unwrap_display()[0]
+    {
+      CODEGEN{checksum, ..} | INLINE{checksum, ..} => checksum.to_vec(),
+      HANDWRITTEN(_) => unreachable!(),
+    };
+    assert_eq!(checksum("<< codegen foo >>\n<< /codegen foo ab12 >>\n"), vec![0xab, 0x12]);
+    assert_eq!(checksum("<< codegen foo >>\n<< /codegen foo >>\n"), vec![]);
+    assert_eq!(checksum("<< codegen beef >>\n<< /codegen beef >>\n"), vec![]);
+    // A single token is a checksum, if it can be one and isn't the identifier
+    assert_eq!(checksum("<< codegen foo >>\n<< /codegen beef >>\n"), vec![0xbe, 0xef]);
+    assert_eq!(checksum("<< codegen beef >>\n<< /codegen beef blake3:ab12 >>\n"), vec![0xab, 0x12]);
+    // Names require a closing delimiter
+    assert!(matches!(&parse("#region gen:af13\n#endregion gen af13\n", &Marker_Style::REGION).unwrap_display()[0], CODEGEN{checksum, ..} if checksum[..] == [0xaf, 0x13]));
+    assert_eq!(checksum("x = /*<< codegen foo >>*/1/*<< /codegen foo ab12 >>*/;\n"), vec![0xab, 0x12]);
+
+    // A deleted begin marker doesn't silently pair the remaining markers
+    let code = "<< codegen a >>\n1\n<< /codegen a >>\n2\n<< /codegen b >>\n";
+    assert_eq!(find(code), Err(Parse_Error::ORPHAN_END_MARKER{location: Location::new(None, 5, 1)}));
+    let code = "<< codegen a >>\n1\n2\n<< /codegen b >>\n";
+    assert_eq!(find(code), Err(Parse_Error::MISMATCHED_END_MARKER{location: Location::new(None, 4, 1), name: "b".to_owned(), begin_location: Box::new(Location::new(None, 1, 1)), identifier: "a".to_owned()}));
+    assert_eq!(find(code).unwrap_err().to_string(), "line 4: end marker named `b` closes section `a` (line 1)");
+    assert!(matches!(find("x = /*<< codegen a >>*/1/*<< /codegen b >>*/;\n"), Err(Parse_Error::MISMATCHED_END_MARKER{..})));
+
+    // With named end markers, a single untagged token is the name
+    let named = |code| parse_sections(code, &Marker_Style::DEFAULT, false, true);
+    assert_eq!(named("<< codegen foo >>\n<< /codegen cafe >>\n"), Err(Parse_Error::MISMATCHED_END_MARKER{location: Location::new(None, 2, 1), name: "cafe".to_owned(), begin_location: Box::new(Location::new(None, 1, 1)), identifier: "foo".to_owned()}));
+    assert!(matches!(named("x = /*<< codegen foo >>*/1/*<< /codegen cafe >>*/;\n"), Err(Parse_Error::MISMATCHED_END_MARKER{..})));
+    assert!(matches!(&named("<< codegen cafe >>\n<< /codegen cafe >>\n").unwrap_display()[0], CODEGEN{checksum, ..} if checksum.is_empty()));
+    assert!(matches!(&named("<< codegen foo >>\n<< /codegen foo cafe >>\n").unwrap_display()[0], CODEGEN{checksum, ..} if checksum[..] == [0xca, 0xfe]));
+    assert!(matches!(&named("<< codegen foo >>\n<< /codegen blake3:cafe >>\n").unwrap_display()[0], CODEGEN{checksum, ..} if checksum[..] == [0xca, 0xfe]));
+    assert!(matches!(&parse_sections("#region gen:foo\n#endregion gen af13\n", &Marker_Style::REGION, false, true).unwrap_display()[0], CODEGEN{checksum, ..} if checksum[..] == [0xaf, 0x13]));
+  }
+
+  #[test]
+  fn test_find_marker_line()
+  {
+    let style = Marker_Style::DEFAULT;
+    assert_eq!(find_marker_line("", &style), None);
+    assert_eq!(find_marker_line("x\n<< codegen\n", &style), None);
+    assert_eq!(find_marker_line("x\n  // << codegen foo >>\n", &style), Some((2, "  // << codegen foo >>")));
+    assert_eq!(find_marker_line("x\r\ny\r\n<< /codegen >>\r\n", &style), Some((3, "<< /codegen >>")));
+  }
+
+  #[test]
+  fn test_escape_markers()
+  {
+    let style = Marker_Style::DEFAULT;
+    assert_eq!(escape_markers("x\n<< codegen\n", &style), "x\n<< codegen\n");
+    assert_eq!(escape_markers("x\n  // << codegen foo >>\n<< /codegen >>", &style), "x\n  // <<\\ codegen foo >>\n<<\\ /codegen >>");
+    assert_eq!(escape_markers("<< codegen a >> << codegen b >>", &style), "<<\\ codegen a >> <<\\ codegen b >>");
+    assert_eq!(escape_markers("#region gen:foo\n#endregion gen", &Marker_Style::REGION), "#\\region gen:foo\n#\\endregion gen");
+    assert_eq!(escape_markers("x = /*<< codegen a >>*/1/*<< /codegen >>*/;", &style), "x = /*<<\\ codegen a >>*/1/*<<\\ /codegen >>*/;");
+
+    let escaped = escape_markers("<< codegen foo >>\nx\n<< /codegen >>\n", &style);
+    assert_eq!(find_marker_line(&escaped, &style), None);
+    assert_eq!(parse(&escaped, &style).unwrap_display(), smallvec![HANDWRITTEN(escaped.as_str())] as Section_List);
+
+    let roundtrip = |code: &str, style: &Marker_Style| assert_eq!(unescape_markers(&escape_markers(code, style), style), code);
+    assert_eq!(escape_markers("<<\\ codegen foo >>\nstd::cout <<\\\n", &style), "<<\\\\ codegen foo >>\nstd::cout <<\\\n");
+    assert_eq!(unescape_markers("<<\\ codegen foo >>\n<<\\\\ /codegen >>\n", &style), "<< codegen foo >>\n<<\\ /codegen >>\n");
+    roundtrip("x\n  // << codegen foo >>\n<< /codegen >>", &style);
+    roundtrip("<<\\ codegen a >> << codegen b >>\r\nstd::cout <<\\\n", &style);
+    roundtrip("x = /*<< codegen a >>*/1/*<<\\ /codegen >>*/;", &style);
+    roundtrip("#region gen:foo\n#\\endregion gen\n#\\ x", &Marker_Style::REGION);
+  }
+
+  #[test]
+  fn test_checksum_algorithm()
+  {
+    let algorithm = |code| match &find(code).unwrap_display()[0]
+    {
+      CODEGEN{checksum_algorithm, checksum, ..} | INLINE{checksum_algorithm, checksum, ..} => (*checksum_algorithm, checksum.to_vec()),
+      HANDWRITTEN(_) => unreachable!(),
+    };
+    assert_eq!(algorithm("<< codegen foo >>\n<< /codegen ab12 >>\n"), (None, vec![0xab, 0x12]));
+    assert_eq!(algorithm("<< codegen foo >>\n<< /codegen sha256:ab12 >>\n"), (Some(Checksum_Algorithm::SHA256), vec![0xab, 0x12]));
+    assert_eq!(algorithm("<< codegen foo >>\n<< /codegen crc32:ab12 >>\n"), (Some(Checksum_Algorithm::CRC32), vec![0xab, 0x12]));
+    assert_eq!(algorithm("<< codegen foo >>1<< /codegen crc32:ab12 >>\n"), (Some(Checksum_Algorithm::CRC32), vec![0xab, 0x12]));
+
+    assert_eq!(find("<< codegen foo >>\n  << /codegen md5:ab12 >>\n"), Err(Parse_Error::UNKNOWN_CHECKSUM_ALGORITHM{location: Location::new(None, 2, 3), tag: "md5".to_owned()}));
+  }
+
+  #[test]
+  fn test_unterminated_section()
+  {
+    let unterminated = |line, column| Err(Parse_Error::UNTERMINATED_SECTION{location: Location::new(None, line, column), identifier: "foo".to_owned()});
+
+    assert_eq!(find("<< codegen foo >>"), unterminated(1, 1));
+    assert_eq!(find("x\n  // << codegen foo >>\ny\n"), unterminated(2, 6));
+    assert_eq!(find("x\n  // << codegen foo >>\ny\n").unwrap_err().with_path(Some(Path::new("src/foo.rs"))).to_string(), "src/foo.rs:2: section `foo` has no end marker");
+  }
+
+  #[test]
+  fn test_orphan_end_marker()
+  {
+    assert_eq!(find("x\n  << /codegen >>\n"), Err(Parse_Error::ORPHAN_END_MARKER{location: Location::new(None, 2, 3)}));
+    assert_eq!(find("<< codegen foo >>\n<< /codegen >>\n<< /codegen >>\n"), Err(Parse_Error::ORPHAN_END_MARKER{location: Location::new(None, 3, 1)}));
+    assert_eq!(find("x\n<< /codegen >>\n").unwrap_err().with_path(Some(Path::new("src/foo.rs"))).to_string(), "src/foo.rs:2: end marker without a begin marker");
+  }
+
+  #[test]
+  fn test_malformed_arguments()
+  {
+    let malformed = |line, column| Err(Parse_Error::MALFORMED_ARGUMENTS{location: Location::new(None, line, column), identifier: "t".to_owned()});
+
+    assert_eq!(find("x\n  // << codegen t(a=\"x) >>\n<< /codegen >>\n"), malformed(2, 6));
+    assert_eq!(find("<< codegen t a=\"x >>\n<< /codegen >>\n"), malformed(1, 1));
+    assert_eq!(find("<< codegen t(a=\"x) >>\n").unwrap_err().to_string(), "line 1: begin marker of section `t` has a malformed argument list");
+    // Without a closing delimiter on the line, it's not a marker
+    assert_eq!(find("std::cout << codegen t(\"x\n"), Ok(smallvec![HANDWRITTEN("std::cout << codegen t(\"x\n")]));
+    assert_eq!(find_marker_line("<< codegen t(a=\"x) >>\n", &Marker_Style::DEFAULT), Some((1, "<< codegen t(a=\"x) >>")));
+  }
+
+  #[test]
+  fn test_nested_begin_marker()
+  {
+    let code = "<< codegen foo >>\nx\n  << codegen bar >>\n<< /codegen >>\n";
+    assert_eq!(find(code), Err(Parse_Error::NESTED_BEGIN_MARKER{
+      location: Location::new(None, 3, 3),
+      identifier: "bar".to_owned(),
+      outer_location: Box::new(Location::new(None, 1, 1)),
+      outer_identifier: "foo".to_owned(),
+    }));
+    assert_eq!(find(code).unwrap_err().with_path(Some(Path::new("src/foo.rs"))).to_string(), "src/foo.rs:3: begin marker of section `bar` inside of section `foo` (src/foo.rs:1). Is the end marker of `foo` missing?");
+  }
   
   #[test]
   fn test_checksum()
@@ -179,8 +636,21 @@ mod test
     assert_eq!(parse_checksum(checksum.to_string().as_str()).as_slice(), checksum.as_bytes());
   }
 
+  fn find(code: &str) -> Result<Section_List<'_>>
+  {
+    parse(code, &Marker_Style::DEFAULT)
+  }
+
   use Indentation as I;
 }
 
-use crate::pest::Parser;
-use crate::indentation::Indentation;
\ No newline at end of file
+use crate::indentation::Indentation;
+use crate::location::Location;
+use crate::checksum::Checksum_Algorithm;
+use std::ops::Range;
+#[cfg(test)]
+use std::path::Path;
+use super::marker_style::requires_separator;
+use super::arguments::{self, Arguments};
+#[cfg(test)]
+use super::arguments::Argument;
@@ -1,81 +1,328 @@
 use super::*;
 
-#[cfg(test)]
+// The marker lines used to be described by a pest grammar. Its delimiters and
+// keywords were string literals compiled into the parser, so they couldn't
+// follow a [`Marker_Style`] chosen at runtime. Since a file is classified one
+// line at a time anyway, matching the configured tokens by hand is simpler
+// than generating a grammar per style and avoids the pest dependencies.
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Line<'a>
 {
   CODE(&'a str),
-  BEGIN_CODEGEN{marker: Marker<'a>, identifier: &'a str,},
-  END_CODEGEN{marker: Marker<'a>, checksum: &'a str,},
+  BEGIN_CODEGEN{marker: Marker<'a>, identifier: &'a str, arguments: Arguments<'a>},
+  /// `name` is empty for end markers without a name
+  END_CODEGEN{marker: Marker<'a>, name: &'a str, checksum: &'a str,},
+  /// `begin_marker ~ code ~ end_marker` within a single line
+  INLINE_CODEGEN{begin: Marker<'a>, identifier: &'a str, arguments: Arguments<'a>, code: &'a str, end: Marker<'a>, end_name: &'a str, checksum: &'a str},
+  /// A begin marker whose argument list can't be parsed, e.g. because of an
+  /// unterminated quote. The `marker` reaches until the end of the line.
+  MALFORMED_BEGIN_MARKER{marker: Marker<'a>, identifier: &'a str},
 }
 
-#[cfg(test)]
-pub fn parse(node: crate::pest::iterators::Pair<Rule>) -> Result<Line>
+/// A marker line of a user region inside of a section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Keep_Line<'a>
 {
-  use Line::*;
-  use Rule::{code_line, begin_marker_line, end_marker_line};
+  BEGIN_KEEP{name: &'a str},
+  END_KEEP,
+}
+
+/// Classifies a single line (without its line break).
+///
+/// A line is a marker line if an occurrence of the opening delimiter starts a
+/// valid marker, see [`marker_starts`]. Otherwise, it's handwritten code. User
+/// region markers are code, too.
+pub fn parse<'a>(line: &'a str, style: &Marker_Style) -> Line<'a>
+{
+  // User region markers may start like an end marker, e.g. `#endregion keep`
+  if parse_keep(line, style).is_some()
+  {
+    return Line::CODE(line);
+  }
+
+  let content = line.trim_start_matches([' ', '\t']);
+  let indentation = Indentation(&line[..line.len()-content.len()]);
 
-  let l = match node.as_rule()
+  let mut malformed = None;
+  for pos in marker_starts(content, style)
   {
-    code_line => CODE(node.as_str()),
-    begin_marker_line =>
+    let before_marker = &content[..pos];
+    let rest = &content[pos+style.open.len()..];
+    let source = |after_marker: &str| &content[pos..content.len()-after_marker.len()];
+
+    let begin_marker = match parse_begin_marker(rest, style)
+    {
+      Some(Ok(begin_marker)) => Some(begin_marker),
+      Some(Err(identifier)) =>
+      {
+        malformed.get_or_insert(Line::MALFORMED_BEGIN_MARKER{marker: Marker{indentation, before_marker, source: &content[pos..], after_marker: ""}, identifier});
+        None
+      }
+      None => None,
+    };
+    if let Some((identifier, arguments, after_marker)) = begin_marker
     {
-      let (marker, identifier) = parse_marker(node);
-      Line::BEGIN_CODEGEN{marker, identifier}
+      if let Some((after_begin, code, end, end_name, checksum)) = parse_inline_rest(before_marker, after_marker, style)
+      {
+        let begin = Marker{indentation, before_marker, source: source(after_marker), after_marker: after_begin};
+        return Line::INLINE_CODEGEN{begin, identifier, arguments, code, end, end_name, checksum};
+      }
+      return Line::BEGIN_CODEGEN{marker: Marker{indentation, before_marker, source: source(after_marker), after_marker}, identifier, arguments};
     }
-    end_marker_line =>
+    if let Some((name, checksum, _, after_marker)) = parse_end_marker(rest, style)
     {
-      let (marker, checksum) = parse_marker(node);
-      Line::END_CODEGEN{marker, checksum}
+      return Line::END_CODEGEN{marker: Marker{indentation, before_marker, source: source(after_marker), after_marker}, name, checksum};
     }
-    _ => unimplemented!("{:?}", node.as_rule()),
+  }
+
+  malformed.unwrap_or(Line::CODE(line))
+}
+
+/// The byte offsets of the opening delimiters in `content`, which may start a
+/// marker. See [`Marker_Style::open_is_operator`].
+fn marker_starts<'a>(content: &'a str, style: &Marker_Style<'a>) -> impl Iterator<Item=usize> + 'a
+{
+  let open = style.open;
+  let max_count = match style.open_is_operator
+  {
+    true => 1,
+    false => usize::MAX,
+  };
+  content.char_indices().map(|(pos, _)| pos).filter(move |&pos| content[pos..].starts_with(open)).take(max_count)
+}
+
+/// Comment delimiters enclosing inline markers. They belong to the markers, not
+/// to the code of the section.
+const INLINE_COMMENTS : [(&str, &str); 5] = [("/*", "*/"), ("<!--", "-->"), ("(*", "*)"), ("{-", "-}"), ("{#", "#}")];
+
+/// Finds the end marker in the text after a begin marker, returning the
+/// closing comment delimiter after the begin marker, the code, the end marker,
+/// its name and its tagged checksum.
+fn parse_inline_rest<'a>(before_marker: &'a str, after_marker: &'a str, style: &Marker_Style) -> Option<(&'a str, &'a str, Marker<'a>, &'a str, &'a str)>
+{
+  let comment = INLINE_COMMENTS.iter().find(|(open, _)| before_marker.trim_end_matches(' ').ends_with(open));
+  let after_begin = match comment
+  {
+    Some((_, close)) if skip_spaces(after_marker).starts_with(close) => &after_marker[..after_marker.len() - skip_spaces(after_marker).len() + close.len()],
+    // Without a closing delimiter, the spaces separate the begin marker from the code
+    _ if style.close.is_empty() => &after_marker[..after_marker.len() - skip_spaces(after_marker).len()],
+    _ => "",
   };
+  let rest = &after_marker[after_begin.len()..];
 
-  return Ok(l);
+  rest.char_indices().filter(|&(pos, _)| rest[pos..].starts_with(style.open)).find_map(|(pos, _)|
+  {
+    let (end_name, checksum, _, after_end) = parse_end_marker(&rest[pos+style.open.len()..], style)?;
+    let before_end = match comment
+    {
+      Some((open, _)) if rest[..pos].trim_end_matches(' ').ends_with(open) => &rest[rest[..pos].trim_end_matches(' ').len()-open.len()..pos],
+      // Without a closing delimiter, the spaces separate the code from the end marker
+      _ if style.close.is_empty() => &rest[rest[..pos].trim_end_matches(' ').len()..pos],
+      _ => "",
+    };
+    let code = &rest[..pos-before_end.len()];
+    let end = Marker{indentation: Indentation(""), before_marker: before_end, source: &rest[pos..rest.len()-after_end.len()], after_marker: after_end};
+    Some((after_begin, code, end, end_name, checksum))
+  })
 }
 
-pub fn parse_begin_marker(node: crate::pest::iterators::Pair<Rule>) -> (Marker, &str)
+/// Classifies a single line (without its line break) as a user region
+/// marker, if it is one.
+pub fn parse_keep<'a>(line: &'a str, style: &Marker_Style) -> Option<Keep_Line<'a>>
 {
-  debug_assert!(node.as_rule() == Rule::begin_marker_line);
-  return parse_marker(node);
+  let content = line.trim_start_matches([' ', '\t']);
+  marker_starts(content, style).find_map(|pos|
+  {
+    let rest = skip_spaces(&content[pos+style.open.len()..]);
+    if let Some(rest) = rest.strip_prefix(style.keep_keyword)
+    {
+      let rest = skip_separator(rest, style.keep_keyword)?;
+      let name = take_while(rest, |c| c == b'_' || c.is_ascii_alphanumeric());
+      if name.is_empty()
+      {
+        return None;
+      }
+      parse_close(&rest[name.len()..], style)?;
+      return Some(Keep_Line::BEGIN_KEEP{name});
+    }
+    parse_close(rest.strip_prefix(style.end_keep_keyword)?, style)?;
+    Some(Keep_Line::END_KEEP)
+  })
 }
 
-pub fn parse_end_marker(node: crate::pest::iterators::Pair<Rule>) -> (Marker, &str)
+/// parses `codegen foo(args) >>` returning the identifier, its arguments and the text after the marker.
+///
+/// If the argument list can't be parsed, but the closing delimiter follows on
+/// the line, the marker is malformed and only the identifier is returned as
+/// error. Without a closing delimiter, the line is code.
+fn parse_begin_marker<'a>(rest: &'a str, style: &Marker_Style) -> Option<Result<(&'a str, Arguments<'a>, &'a str), &'a str>>
 {
-  debug_assert!(node.as_rule() == Rule::end_marker_line);
-  return parse_marker(node);
+  let rest = skip_spaces(rest).strip_prefix(style.begin_keyword)?;
+  let rest = skip_separator(rest, style.begin_keyword)?;
+
+  let identifier = take_while(rest, |c| c == b'_' || c.is_ascii_alphanumeric());
+  if identifier.is_empty()
+  {
+    return None;
+  }
+
+  let rest = &rest[identifier.len()..];
+  let malformed = || match !style.close.is_empty() && rest.contains(style.close)
+  {
+    true => Some(Err(identifier)),
+    false => None,
+  };
+  let (arguments, rest) = match arguments::parse_parenthesized(rest)
+  {
+    Some(x) => x,
+    None if rest.starts_with('(') => return malformed(),
+    // Without a closing delimiter, there is no way to tell arguments and the text after the marker apart
+    None if style.close.is_empty() => (Arguments::default(), rest),
+    None => match arguments::parse_space_separated(rest, style.close)
+    {
+      Some(x) => x,
+      None => return malformed(),
+    },
+  };
+
+  let after_marker = parse_close(rest, style)?;
+  Some(Ok((identifier, arguments, after_marker)))
 }
 
-fn parse_marker(node: crate::pest::iterators::Pair<Rule>) -> (Marker, &str)
+/// The byte range of the tagged checksum within the `source` of an end marker.
+///
+/// Without a checksum, the range is empty and starts right after the keyword
+/// or the name. A single token equal to `identifier` is the name.
+pub fn checksum_span(source: &str, identifier: &str, style: &Marker_Style) -> Option<Range<usize>>
 {
-  debug_assert!(node.as_rule() == Rule::begin_marker_line || node.as_rule() == Rule::end_marker_line);
-  let mut xs = node.into_inner();
+  let rest = source.strip_prefix(style.open)?;
+  let (name, checksum, start, _) = parse_end_marker(rest, style)?;
+  let start = style.open.len() + start;
+  match name.is_empty() && checksum == identifier && !style.close.is_empty()
+  {
+    true => Some(start+checksum.len()..start+checksum.len()),
+    false => Some(start..start+checksum.len()),
+  }
+}
 
-  let indentation = xs.next().unwrap();
-  let before_marker = xs.next().unwrap();
+/// The name within the `source` of an end marker of the section `identifier`.
+///
+/// A single token like `<< /codegen cafe >>` is parsed as checksum, if it can
+/// be one, but returned here, if it's the identifier.
+pub fn end_marker_name<'a>(source: &'a str, identifier: &str, style: &Marker_Style) -> Option<&'a str>
+{
+  let (name, checksum, _, _) = parse_end_marker(source.strip_prefix(style.open)?, style)?;
+  match name.is_empty() && checksum == identifier && !style.close.is_empty()
+  {
+    true => Some(checksum),
+    false => Some(name),
+  }
+}
 
-  debug_assert_eq!(indentation.as_rule(), Rule::indentation);
-  let indentation = Indentation(indentation.as_str().len());
+/// parses `/codegen [name] [[tag:]checksum] >>` returning the name, the
+/// tagged checksum, its offset in `rest` and the text after the marker.
+///
+/// A single token is a checksum, if it can be one. Names require a closing
+/// delimiter, as they can't be told apart from the text after the marker
+/// otherwise.
+fn parse_end_marker<'a>(rest: &'a str, style: &Marker_Style) -> Option<(&'a str, &'a str, usize, &'a str)>
+{
+  let marker = rest;
+  let offset = |text: &str| marker.len() - text.len();
+  let rest = skip_spaces(rest).strip_prefix(style.end_keyword)?;
 
-  debug_assert_eq!(before_marker.as_rule(), Rule::before_marker);
-  let before_marker = before_marker.as_str();
-  
-  let identifier = xs.next().unwrap();
-  let (identifier, after_marker) = match identifier.as_rule()
+  if let Some(with_checksum) = skip_separator(rest, style.end_keyword).filter(|x| x.len() < rest.len() || !requires_separator(style.end_keyword))
   {
-    Rule::identifier | Rule::checksum =>
+    if let Some((checksum, after_marker)) = parse_checksum_and_close(with_checksum, style)
     {
-      let after_marker = xs.next().unwrap();
-      debug_assert_eq!(after_marker.as_rule(), Rule::after_marker);
-      let after_marker = after_marker.as_str();
+      return Some(("", checksum, offset(with_checksum), after_marker));
+    }
 
-      (identifier.as_str(), after_marker)
+    let name = take_while(with_checksum, |c| c == b'_' || c.is_ascii_alphanumeric());
+    if !name.is_empty() && !style.close.is_empty()
+    {
+      let after_name = &with_checksum[name.len()..];
+      if let Some(with_checksum) = skip_separator(after_name, name)
+      {
+        if let Some((checksum, after_marker)) = parse_checksum_and_close(with_checksum, style)
+        {
+          return Some((name, checksum, offset(with_checksum), after_marker));
+        }
+      }
+      if let Some(after_marker) = parse_close(after_name, style)
+      {
+        return Some((name, "", offset(after_name), after_marker));
+      }
     }
-    Rule::after_marker => ("", identifier.as_str()),
-    _ => unreachable!("Rule::{:?} span: {:?}", identifier.as_rule(), identifier.as_str()),
+  }
+
+  let after_marker = parse_close(rest, style)?;
+  Some(("", "", offset(rest), after_marker))
+}
+
+/// parses `[tag:]checksum >>` returning the tagged checksum and the text after
+/// the marker
+fn parse_checksum_and_close<'a>(text: &'a str, style: &Marker_Style) -> Option<(&'a str, &'a str)>
+{
+  let tag = take_while(text, |c| c.is_ascii_lowercase() || c.is_ascii_digit());
+  let tag_len = match !tag.is_empty() && text[tag.len()..].starts_with(':')
+  {
+    true => tag.len() + 1,
+    false => 0,
   };
+  let hex_digits = take_while(&text[tag_len..], |c| c.is_ascii_hexdigit());
+  let hex_len = (hex_digits.len()/2*2).min(64);
+  if hex_len == 0
+  {
+    return None;
+  }
+  let checksum = &text[..tag_len+hex_len];
+  let after_marker = parse_close(&text[checksum.len()..], style)?;
+  Some((checksum, after_marker))
+}
 
-  (Marker{indentation, before_marker, after_marker}, identifier)
+/// Without a closing delimiter, the text after the marker keeps its leading
+/// spaces, so rewriting the marker doesn't change them.
+fn parse_close<'a>(rest: &'a str, style: &Marker_Style) -> Option<&'a str>
+{
+  if !style.close.is_empty()
+  {
+    return skip_spaces(rest).strip_prefix(style.close);
+  }
+
+  // `#endregion genfoo` must not be mistaken for a marker
+  match starts_with_word(rest)
+  {
+    true => None,
+    false => Some(rest),
+  }
+}
+
+pub fn starts_with_word(text: &str) -> bool
+{
+  text.bytes().next().map_or(false, |c| c == b'_' || c.is_ascii_alphanumeric())
+}
+
+fn skip_separator<'a>(rest: &'a str, keyword: &str) -> Option<&'a str>
+{
+  let skipped = skip_spaces(rest);
+  match requires_separator(keyword) && skipped.len() == rest.len()
+  {
+    true => None,
+    false => Some(skipped),
+  }
+}
+
+fn skip_spaces(rest: &str) -> &str
+{
+  rest.trim_start_matches(' ')
+}
+
+fn take_while(text: &str, f: impl Fn(u8) -> bool) -> &str
+{
+  let len = text.bytes().take_while(|&c| f(c)).count();
+  &text[..len]
 }
 
 #[cfg(test)]
@@ -84,28 +331,166 @@ mod test
   use super::*;
 
   #[test]
-  fn lines() -> Result
+  fn lines()
+  {
+    let indentation = I("  ");
+
+    assert_eq!(parse_line(""), Line::CODE(""));
+    assert_eq!(parse_line("xyz"), Line::CODE("xyz"));
+    assert_eq!(parse_line("  // << codegen foo >> let's go!"), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation, before_marker: "// ", source: "<< codegen foo >>", after_marker: " let's go!"}});
+    assert_eq!(parse_line("  // << /codegen f00baa >> nice!"), Line::END_CODEGEN{name: "", checksum: "f00baa", marker: Marker{indentation, before_marker: "// ", source: "<< /codegen f00baa >>", after_marker: " nice!"}});
+    assert_eq!(parse_line("  # << /codegen 0123465789abcdef00112233445566778899aabbccddeefffedcba9876543210 >>"), Line::END_CODEGEN{name: "", checksum: "0123465789abcdef00112233445566778899aabbccddeefffedcba9876543210", marker: Marker{indentation, before_marker: "# ", source: "<< /codegen 0123465789abcdef00112233445566778899aabbccddeefffedcba9876543210 >>", after_marker: ""}});
+    assert_eq!(parse_line("  // << /codegen >> nice!"), Line::END_CODEGEN{name: "", checksum: "", marker: Marker{indentation, before_marker: "// ", source: "<< /codegen >>", after_marker: " nice!"}});
+    assert_eq!(parse_line("  // << /codegen sha256:f00baa >>"), Line::END_CODEGEN{name: "", checksum: "sha256:f00baa", marker: Marker{indentation, before_marker: "// ", source: "<< /codegen sha256:f00baa >>", after_marker: ""}});
+    assert_eq!(parse_line("  // << /codegen>> nice!"), Line::END_CODEGEN{name: "", checksum: "", marker: Marker{indentation, before_marker: "// ", source: "<< /codegen>>", after_marker: " nice!"}});
+
+    assert_eq!(parse_line("\t// << codegen foo >>"), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I("\t"), before_marker: "// ", source: "<< codegen foo >>", after_marker: ""}});
+    assert_eq!(parse_line(" \t << /codegen >>"), Line::END_CODEGEN{name: "", checksum: "", marker: Marker{indentation: I(" \t "), before_marker: "", source: "<< /codegen >>", after_marker: ""}});
+  }
+
+  #[test]
+  fn not_a_marker()
+  {
+    assert_eq!(parse_line("<< codegen >>"), Line::CODE("<< codegen >>"));
+    assert_eq!(parse_line("<< codegenfoo >>"), Line::CODE("<< codegenfoo >>"));
+    assert_eq!(parse_line("<< codegen foo"), Line::CODE("<< codegen foo"));
+    assert_eq!(parse_line("<< /codegen x-y >>"), Line::CODE("<< /codegen x-y >>"));
+    assert_eq!(parse_line("<< /codegen xyz abc >>"), Line::CODE("<< /codegen xyz abc >>"));
+    assert_eq!(parse_line("<< /codegenab12 >>"), Line::CODE("<< /codegenab12 >>"));
+    assert_eq!(parse_line("<< /codegen xyz sha256: >>"), Line::CODE("<< /codegen xyz sha256: >>"));
+    assert_eq!(parse_line("<< /codegen sha256: >>"), Line::CODE("<< /codegen sha256: >>"));
+    assert_eq!(parse_line("<< /codegen SHA256:ab >>"), Line::CODE("<< /codegen SHA256:ab >>"));
+  }
+
+  #[test]
+  fn named_end_markers()
+  {
+    let end = |name, checksum, source| Line::END_CODEGEN{name, checksum, marker: Marker{indentation: I(""), before_marker: "", source, after_marker: ""}};
+
+    assert_eq!(parse_line("<< /codegen xyz >>"), end("xyz", "", "<< /codegen xyz >>"));
+    assert_eq!(parse_line("<< /codegen abc >>"), end("abc", "", "<< /codegen abc >>"));
+    assert_eq!(parse_line("<<  /codegen foo  ab12>>"), end("foo", "ab12", "<<  /codegen foo  ab12>>"));
+    assert_eq!(parse_line("<< /codegen beef cd34 >>"), end("beef", "cd34", "<< /codegen beef cd34 >>"));
+    // Resolved by the file parser, which knows the identifier
+    assert_eq!(parse_line("<< /codegen beef >>"), end("", "beef", "<< /codegen beef >>"));
+    assert_eq!(super::parse("#endregion gen foo", &Marker_Style::REGION), Line::END_CODEGEN{name: "", checksum: "", marker: Marker{indentation: I(""), before_marker: "", source: "#endregion gen", after_marker: " foo"}});
+
+    assert_eq!(checksum_span("<< /codegen foo ab12 >>", "foo", &Marker_Style::DEFAULT), Some(16..20));
+    assert_eq!(checksum_span("<< /codegen foo >>", "foo", &Marker_Style::DEFAULT), Some(15..15));
+    assert_eq!(checksum_span("<< /codegen beef >>", "beef", &Marker_Style::DEFAULT), Some(16..16));
+    assert_eq!(checksum_span("<< /codegen beef >>", "foo", &Marker_Style::DEFAULT), Some(12..16));
+
+    assert_eq!(end_marker_name("<< /codegen foo ab12 >>", "foo", &Marker_Style::DEFAULT), Some("foo"));
+    assert_eq!(end_marker_name("<< /codegen xyz >>", "foo", &Marker_Style::DEFAULT), Some("xyz"));
+    assert_eq!(end_marker_name("<< /codegen beef >>", "beef", &Marker_Style::DEFAULT), Some("beef"));
+    assert_eq!(end_marker_name("<< /codegen beef >>", "foo", &Marker_Style::DEFAULT), Some(""));
+    assert_eq!(end_marker_name("<< /codegen blake3:beef >>", "beef", &Marker_Style::DEFAULT), Some(""));
+    assert_eq!(end_marker_name("#endregion gen beef", "beef", &Marker_Style::REGION), Some(""));
+  }
+
+  #[test]
+  fn multiple_delimiters()
+  {
+    // With the default style, only the first `<<` may start a marker, as it's an operator
+    assert_eq!(parse_line("std::cout << x; // << codegen foo >>"), Line::CODE("std::cout << x; // << codegen foo >>"));
+    assert_eq!(parse_line("std::cout << x; // << /codegen >>"), Line::CODE("std::cout << x; // << /codegen >>"));
+    assert_eq!(parse_line("a<<< codegen foo >>"), Line::CODE("a<<< codegen foo >>"));
+    assert_eq!(parse_keep("x << 1; // << keep foo >>", &Marker_Style::DEFAULT), None);
+
+    // The rule belongs to the delimiter, not to the preset
+    let tweaked = Marker_Style{padding: "", ..Marker_Style::DEFAULT};
+    assert_eq!(super::parse("std::cout << x << y; // << codegen foo >>", &tweaked), Line::CODE("std::cout << x << y; // << codegen foo >>"));
+    assert!(matches!(super::parse("std::cout << x; // << codegen foo >>", &Marker_Style{open_is_operator: false, ..tweaked}), Line::BEGIN_CODEGEN{identifier: "foo", ..}));
+
+    let parse = |line| super::parse(line, &Marker_Style::BRACKETS);
+    assert_eq!(parse("x[[[0]]]; // [[[ codegen foo ]]]"), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I(""), before_marker: "x[[[0]]]; // ", source: "[[[ codegen foo ]]]", after_marker: ""}});
+    assert_eq!(parse("a[[[[ codegen foo ]]]"), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I(""), before_marker: "a[", source: "[[[ codegen foo ]]]", after_marker: ""}});
+  }
+
+  #[test]
+  fn styles()
+  {
+    let parse = |line, style| super::parse(line, &style);
+
+    assert_eq!(parse("// [[[ codegen foo ]]]", Marker_Style::BRACKETS), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I(""), before_marker: "// ", source: "[[[ codegen foo ]]]", after_marker: ""}});
+    assert_eq!(parse("// [[[ /codegen ab12 ]]] x", Marker_Style::BRACKETS), Line::END_CODEGEN{name: "", checksum: "ab12", marker: Marker{indentation: I(""), before_marker: "// ", source: "[[[ /codegen ab12 ]]]", after_marker: " x"}});
+    assert_eq!(parse("// << codegen foo >>", Marker_Style::BRACKETS), Line::CODE("// << codegen foo >>"));
+
+    assert_eq!(parse("  #region gen:foo", Marker_Style::REGION), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I("  "), before_marker: "", source: "#region gen:foo", after_marker: ""}});
+    assert_eq!(parse("  #region gen: foo bar", Marker_Style::REGION), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I("  "), before_marker: "", source: "#region gen: foo", after_marker: " bar"}});
+    assert_eq!(parse("  #endregion gen", Marker_Style::REGION), Line::END_CODEGEN{name: "", checksum: "", marker: Marker{indentation: I("  "), before_marker: "", source: "#endregion gen", after_marker: ""}});
+    assert_eq!(parse("  #endregion gen af13", Marker_Style::REGION), Line::END_CODEGEN{name: "", checksum: "af13", marker: Marker{indentation: I("  "), before_marker: "", source: "#endregion gen af13", after_marker: ""}});
+    assert_eq!(parse("  #endregion genaf13", Marker_Style::REGION), Line::CODE("  #endregion genaf13"));
+    assert_eq!(parse("  #region gen:foo(", Marker_Style::REGION), Line::CODE("  #region gen:foo("));
+    assert_eq!(parse("  #region gen:foo x", Marker_Style::REGION), Line::BEGIN_CODEGEN{identifier: "foo", arguments: Arguments::default(), marker: Marker{indentation: I("  "), before_marker: "", source: "#region gen:foo", after_marker: " x"}});
+  }
+
+  #[test]
+  fn inline()
+  {
+    let inline = |begin, code, end: Marker<'static>, checksum| Line::INLINE_CODEGEN{begin, identifier: "sizes", arguments: Arguments::default(), code, end, end_name: "", checksum};
+    let marker = |indentation, before_marker, source, after_marker| Marker{indentation: I(indentation), before_marker, source, after_marker};
+
+    assert_eq!(parse_line("int sizes[] = {/*<< codegen sizes >>*/1,2,3/*<< /codegen >>*/};"), inline(marker("", "int sizes[] = {/*", "<< codegen sizes >>", "*/"), "1,2,3", marker("", "/*", "<< /codegen >>", "*/};"), ""));
+    assert_eq!(parse_line("  x = /* << codegen sizes >> */ 1 /* << /codegen ab12 >> */;"), inline(marker("  ", "x = /* ", "<< codegen sizes >>", " */"), " 1 ", marker("", "/* ", "<< /codegen ab12 >>", " */;"), "ab12"));
+    assert_eq!(parse_line("<< codegen sizes >><< /codegen >>"), inline(marker("", "", "<< codegen sizes >>", ""), "", marker("", "", "<< /codegen >>", ""), ""));
+    assert_eq!(super::parse("#region gen:sizes 1,2 #endregion gen", &Marker_Style::REGION), inline(marker("", "", "#region gen:sizes", " "), "1,2", marker("", " ", "#endregion gen", ""), ""));
+    assert_eq!(super::parse("#region gen:sizes;#endregion gen", &Marker_Style::REGION), inline(marker("", "", "#region gen:sizes", ""), ";", marker("", "", "#endregion gen", ""), ""));
+  }
+
+  #[test]
+  fn keep()
   {
-    let indentation = I(2);
+    let keep = |line, style| parse_keep(line, &style);
 
-    assert_eq!(parse_line("")?, Line::CODE(""));
-    assert_eq!(parse_line("xyz")?, Line::CODE("xyz"));
-    assert_eq!(parse_line("  // << codegen foo >> let's go!")?, Line::BEGIN_CODEGEN{identifier: "foo", marker: Marker{indentation, before_marker: "// ", after_marker: " let's go!"}});
-    assert_eq!(parse_line("  // << /codegen f00baa >> nice!")?, Line::END_CODEGEN{checksum: "f00baa", marker: Marker{indentation, before_marker: "// ", after_marker: " nice!"}});
-    assert_eq!(parse_line("  # << /codegen 0123465789abcdef00112233445566778899aabbccddeefffedcba9876543210 >>")?, Line::END_CODEGEN{checksum: "0123465789abcdef00112233445566778899aabbccddeefffedcba9876543210", marker: Marker{indentation, before_marker: "# ", after_marker: ""}});
-    assert_eq!(parse_line("  // << /codegen >> nice!")?, Line::END_CODEGEN{checksum: "", marker: Marker{indentation, before_marker: "// ", after_marker: " nice!"}});
-    assert_eq!(parse_line("  // << /codegen>> nice!")?, Line::END_CODEGEN{checksum: "", marker: Marker{indentation, before_marker: "// ", after_marker: " nice!"}});
+    assert_eq!(keep("  // << keep body >>", Marker_Style::DEFAULT), Some(Keep_Line::BEGIN_KEEP{name: "body"}));
+    assert_eq!(keep("  // <</keep>> x", Marker_Style::DEFAULT), Some(Keep_Line::END_KEEP));
+    assert_eq!(keep("  // << keepbody >>", Marker_Style::DEFAULT), None);
+    assert_eq!(keep("  // << keep >>", Marker_Style::DEFAULT), None);
+    assert_eq!(keep("  // << codegen foo >>", Marker_Style::DEFAULT), None);
+    assert_eq!(keep("#region keep:body", Marker_Style::REGION), Some(Keep_Line::BEGIN_KEEP{name: "body"}));
+    assert_eq!(keep("#endregion keep", Marker_Style::REGION), Some(Keep_Line::END_KEEP));
+    assert_eq!(keep("#endregion keeper", Marker_Style::REGION), None);
 
-    Ok(())
+    assert_eq!(parse_line("// << keep body >>"), Line::CODE("// << keep body >>"));
+    assert_eq!(super::parse("#endregion keep", &Marker_Style::REGION), Line::CODE("#endregion keep"));
   }
 
+  #[test]
+  fn test_checksum_span()
+  {
+    let span = |source| checksum_span(source, "foo", &Marker_Style::DEFAULT);
+    assert_eq!(span("<< /codegen ab12 >>"), Some(12..16));
+    assert_eq!(span("<<  /codegen   sha256:ab12>>"), Some(15..26));
+    assert_eq!(span("<< /codegen >>"), Some(11..11));
+    assert_eq!(span("<</codegen>>"), Some(10..10));
+    assert_eq!(span("<< codegen foo >>"), None);
+    assert_eq!(checksum_span("#endregion gen af13", "af13", &Marker_Style::REGION), Some(15..19));
+  }
 
-  fn parse_line(code: &str) -> Result<Line>
+  #[test]
+  fn arguments()
   {
-    let mut result = Section_Parser::parse(Rule::line, code)?;
+    let marker = |source| Marker{indentation: I(""), before_marker: "// ", source, after_marker: " x"};
+    let arguments = |source, list: &[Argument<'static>]| Arguments{source, list: list.iter().copied().collect()};
+    let users = Argument{name: None, value: "users"};
+    let columns = Argument{name: Some("columns"), value: "id,name"};
 
-    super::parse(result.next().unwrap().into_inner().next().unwrap())
+    assert_eq!(parse_line("// << codegen table(users, columns=\"id,name\") >> x"), Line::BEGIN_CODEGEN{identifier: "table", arguments: arguments("(users, columns=\"id,name\")", &[users, columns]), marker: marker("<< codegen table(users, columns=\"id,name\") >>")});
+    assert_eq!(parse_line("// << codegen table users columns=id,name >> x"), Line::BEGIN_CODEGEN{identifier: "table", arguments: arguments("users columns=id,name", &[users, columns]), marker: marker("<< codegen table users columns=id,name >>")});
+    assert_eq!(parse_line("// << codegen table() >> x"), Line::BEGIN_CODEGEN{identifier: "table", arguments: arguments("()", &[]), marker: marker("<< codegen table() >>")});
+    assert_eq!(super::parse("// #region gen:table(users) x", &Marker_Style::REGION), Line::BEGIN_CODEGEN{identifier: "table", arguments: arguments("(users)", &[users]), marker: Marker{source: "#region gen:table(users)", after_marker: " x", ..marker("")}});
+
+    assert_eq!(parse_line("// << codegen table(users >> x"), Line::MALFORMED_BEGIN_MARKER{identifier: "table", marker: Marker{source: "<< codegen table(users >> x", after_marker: "", ..marker("")}});
+    assert_eq!(parse_line("// << codegen table(a=\"x) >>"), Line::MALFORMED_BEGIN_MARKER{identifier: "table", marker: Marker{source: "<< codegen table(a=\"x) >>", after_marker: "", ..marker("")}});
+    assert_eq!(parse_line("// << codegen table(users"), Line::CODE("// << codegen table(users"));
+    assert_eq!(parse_line("// << codegen table users>x"), Line::CODE("// << codegen table users>x"));
+  }
+
+  fn parse_line(code: &str) -> Line<'_>
+  {
+    super::parse(code, &Marker_Style::DEFAULT)
   }
 
   use Indentation as I;
-}
\ No newline at end of file
+}
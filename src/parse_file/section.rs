@@ -4,16 +4,126 @@ use super::*;
 pub enum Section<'a>
 {
   HANDWRITTEN(&'a str),
-  CODEGEN{identifier: &'a str, code: &'a str, checksum: ArrayVec<u8, 32>, begin: Marker<'a>, end: Marker<'a>},
+  CODEGEN
+  {
+    identifier: &'a str,
+    arguments: Arguments<'a>,
+    /// The code between the markers, including nested sections
+    code: &'a str,
+    checksum: ArrayVec<u8, 32>,
+    checksum_algorithm: Option<Checksum_Algorithm>,
+    begin: Marker<'a>,
+    end: Marker<'a>,
+    /// The sections `code` consists of, if it contains nested sections.
+    /// Otherwise empty. Only filled by [`find_nested`](crate::parse_file::find_nested).
+    children: Vec<Section<'a>>,
+  },
+  /// A section within a single line, e.g.
+  /// `int sizes[] = {/*<< codegen sizes >>*/1,2,3/*<< /codegen >>*/};`.
+  ///
+  /// The line consists of `begin`, `code` and `end`. The `after_marker` of
+  /// `begin` is the comment delimiter closing the begin marker, if any. `end`
+  /// has no indentation. Its `before_marker` is the comment delimiter opening
+  /// the end marker, if any.
+  INLINE
+  {
+    identifier: &'a str,
+    arguments: Arguments<'a>,
+    code: &'a str,
+    checksum: ArrayVec<u8, 32>,
+    checksum_algorithm: Option<Checksum_Algorithm>,
+    begin: Marker<'a>,
+    end: Marker<'a>,
+  },
+}
+
+/// The fields of a [`Section::CODEGEN`] or [`Section::INLINE`], see
+/// [`Section::parts`]
+#[derive(Clone, Copy, Debug)]
+pub struct Section_Parts<'s, 'a>
+{
+  pub identifier: &'a str,
+  pub arguments: &'s Arguments<'a>,
+  pub code: &'a str,
+  pub checksum: &'s ArrayVec<u8, 32>,
+  pub checksum_algorithm: Option<Checksum_Algorithm>,
+  pub begin: &'s Marker<'a>,
+  pub end: &'s Marker<'a>,
+  /// Always empty for inline sections
+  pub children: &'s [Section<'a>],
+  pub inline: bool,
+}
+
+impl<'a> Section<'a>
+{
+  /// The fields of a generated section, `None` for handwritten code
+  pub fn parts(&self) -> Option<Section_Parts<'_, 'a>>
+  {
+    use Section::*;
+    match self
+    {
+      HANDWRITTEN(_) => None,
+      CODEGEN{identifier, arguments, code, checksum, checksum_algorithm, begin, end, children} => Some(Section_Parts{identifier, arguments, code, checksum, checksum_algorithm: *checksum_algorithm, begin, end, children, inline: false}),
+      INLINE{identifier, arguments, code, checksum, checksum_algorithm, begin, end} => Some(Section_Parts{identifier, arguments, code, checksum, checksum_algorithm: *checksum_algorithm, begin, end, children: &[], inline: true}),
+    }
+  }
+
+  /// The handwritten code or the code between the markers
+  pub fn code(&self) -> &'a str
+  {
+    use Section::*;
+    match *self
+    {
+      HANDWRITTEN(code) | CODEGEN{code, ..} | INLINE{code, ..} => code,
+    }
+  }
+
+  /// The number of lines the section spans in the file, including its marker
+  /// lines
+  pub fn line_count(&self) -> usize
+  {
+    use Section::*;
+    let code_lines = self.code().bytes().filter(|&c| c == b'\n').count();
+    match self
+    {
+      HANDWRITTEN(_) => code_lines,
+      CODEGEN{..} => 2 + code_lines,
+      INLINE{..} => 1,
+    }
+  }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Marker<'a>
 {
-  pub indentation: Indentation,
+  pub indentation: Indentation<'a>,
   pub before_marker: &'a str,
+  /// The marker itself as written in the file, from the opening delimiter to
+  /// the closing delimiter
+  pub source: &'a str,
   pub after_marker: &'a str,
 }
 
+impl Marker<'_>
+{
+  /// 1-based column of the opening delimiter, counted in characters
+  pub fn column(&self) -> usize
+  {
+    self.indentation.0.chars().count() + self.before_marker.chars().count() + 1
+  }
+}
+
+/// Writes the whole marker line, without its line break
+impl fmt::Display for Marker<'_>
+{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(f, "{}{}{}{}", self.indentation, self.before_marker, self.source, self.after_marker)
+  }
+}
+
 pub type Section_List<'a> = SmallVec<[Section<'a> ; 8]>;
-use crate::indentation::Indentation;
\ No newline at end of file
+use crate::indentation::Indentation;
+use super::Arguments;
+use crate::checksum::Checksum_Algorithm;
+use std::fmt;
\ No newline at end of file
@@ -0,0 +1,229 @@
+/// A part of the code of a section, which is written by hand and kept when
+/// the section is regenerated.
+///
+/// It is enclosed by `<< keep name >>` and `<< /keep >>` (see
+/// [`Marker_Style::keep_keyword`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct User_Region<'a>
+{
+  pub name: &'a str,
+  /// The byte range of the lines between the markers
+  pub code: Range<usize>,
+  /// 1-based line number of the begin marker within the code of the section
+  pub line: usize,
+  /// The indentation of the begin marker
+  pub indentation: Indentation<'a>,
+  /// Whether the region is inside of a nested section and belongs to it
+  pub nested: bool,
+}
+
+/// The line numbers are 1-based and relative to the code of the section
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum User_Region_Error
+{
+  #[error("user region `{name}` without an end marker")]
+  UNTERMINATED{name: String, line: usize},
+  #[error("a user region end marker without a begin marker")]
+  ORPHAN_END_MARKER{line: usize},
+  #[error("user region `{name}` inside of user region `{outer_name}`")]
+  NESTED{name: String, outer_name: String, line: usize},
+  #[error("a second user region `{name}`")]
+  DUPLICATE{name: String, line: usize},
+  /// The old code has a user region, the generated code has not
+  #[error("user region `{name}`, which is missing in the generated code")]
+  MISSING{name: String, line: usize},
+}
+
+impl User_Region_Error
+{
+  /// The line of the offending marker
+  pub fn line(&self) -> usize
+  {
+    use User_Region_Error::*;
+    match self
+    {
+      UNTERMINATED{line, ..} | ORPHAN_END_MARKER{line} | NESTED{line, ..} | DUPLICATE{line, ..} | MISSING{line, ..} => *line,
+    }
+  }
+}
+
+/// Finds the user regions in the code of a section.
+///
+/// `code` is the code between the markers of a section, not the whole file.
+pub fn find_user_regions<'a>(code: &'a str, style: &Marker_Style) -> Result<Vec<User_Region<'a>>, User_Region_Error>
+{
+  let mut regions : Vec<User_Region> = Vec::new();
+  let mut open : Option<User_Region> = None;
+  let mut depth = 0_usize;
+  let mut offset = 0;
+  for (index, line) in code.split_inclusive('\n').enumerate()
+  {
+    let line_number = index + 1;
+    let end = offset + line.len();
+    match (parse_keep(line.trim_end_matches(['\n', '\r']), style), open.take())
+    {
+      (Some(Keep_Line::BEGIN_KEEP{name}), None) =>
+      {
+        let indentation = Indentation(&line[..line.len()-line.trim_start_matches([' ', '\t']).len()]);
+        open = Some(User_Region{name, code: end..end, line: line_number, indentation, nested: depth > 0});
+      }
+      (Some(Keep_Line::BEGIN_KEEP{name}), Some(outer)) => return Err(User_Region_Error::NESTED{name: name.to_owned(), outer_name: outer.name.to_owned(), line: line_number}),
+      (Some(Keep_Line::END_KEEP), Some(region)) => regions.push(User_Region{code: region.code.start..offset, ..region}),
+      (Some(Keep_Line::END_KEEP), None) => return Err(User_Region_Error::ORPHAN_END_MARKER{line: line_number}),
+      // Marker lines inside of a user region are handwritten code
+      (None, Some(region)) => open = Some(region),
+      (None, None) => match parse_line(line.trim_end_matches(['\n', '\r']), style)
+      {
+        Line::BEGIN_CODEGEN{..} => depth += 1,
+        Line::END_CODEGEN{..} => depth = depth.saturating_sub(1),
+        Line::CODE(_) | Line::INLINE_CODEGEN{..} | Line::MALFORMED_BEGIN_MARKER{..} => (),
+      },
+    }
+    offset = end;
+  }
+
+  if let Some(region) = open
+  {
+    return Err(User_Region_Error::UNTERMINATED{name: region.name.to_owned(), line: region.line});
+  }
+
+  let own = || regions.iter().filter(|region| !region.nested);
+  if let Some(duplicate) = own().enumerate().find_map(|(index, region)| own().take(index).any(|other| other.name == region.name).then_some(region))
+  {
+    return Err(User_Region_Error::DUPLICATE{name: duplicate.name.to_owned(), line: duplicate.line});
+  }
+
+  Ok(regions)
+}
+
+/// The code with the content of all user regions removed, as covered by the
+/// checksum
+pub fn strip_user_regions<'a>(code: &'a str, style: &Marker_Style) -> Result<Cow<'a, str>, User_Region_Error>
+{
+  let regions = find_user_regions(code, style)?;
+  if regions.iter().all(|region| region.code.is_empty())
+  {
+    return Ok(Cow::Borrowed(code));
+  }
+
+  let mut stripped = String::with_capacity(code.len());
+  let mut offset = 0;
+  for region in regions.iter()
+  {
+    stripped += &code[offset..region.code.start];
+    offset = region.code.end;
+  }
+  stripped += &code[offset..];
+  Ok(Cow::Owned(stripped))
+}
+
+/// Copies the content of the user regions of `old_code` into the regions of
+/// the same name in `new_code`.
+///
+/// Regions of `new_code` missing in `old_code` keep their generated content.
+/// If the begin marker of a region moved to a different indentation, e.g.
+/// because the generator wrapped it into another block, the indentation of its
+/// lines is changed the same way.
+/// Regions of `old_code` missing in `new_code` are an error, as their content
+/// would be lost.
+pub fn restore_user_regions(new_code: &str, old_code: &str, style: &Marker_Style) -> Result<String, User_Region_Error>
+{
+  let old_regions = find_user_regions(old_code, style)?;
+  let new_regions = find_user_regions(new_code, style)?;
+  let old_regions = old_regions.iter().filter(|region| !region.nested);
+  let new_regions = || new_regions.iter().filter(|region| !region.nested);
+
+  if let Some(missing) = old_regions.clone().find(|old| new_regions().all(|new| new.name != old.name))
+  {
+    return Err(User_Region_Error::MISSING{name: missing.name.to_owned(), line: missing.line});
+  }
+
+  let mut restored = String::with_capacity(new_code.len());
+  let mut offset = 0;
+  for new in new_regions()
+  {
+    if let Some(old) = old_regions.clone().find(|old| old.name == new.name)
+    {
+      restored += &new_code[offset..new.code.start];
+      restored += &reindent(&old_code[old.code.clone()], old.indentation, new.indentation);
+      offset = new.code.end;
+    }
+  }
+  restored += &new_code[offset..];
+  Ok(restored)
+}
+
+/// Replaces the indentation `old` of the lines of `code` by `new`.
+///
+/// Lines indented less than `old` are kept as they are.
+fn reindent<'a>(code: &'a str, old: Indentation, new: Indentation) -> Cow<'a, str>
+{
+  if old == new
+  {
+    return Cow::Borrowed(code);
+  }
+
+  let mut reindented = String::with_capacity(code.len());
+  for line in code.split_inclusive('\n')
+  {
+    match line.strip_prefix(old.0)
+    {
+      Some(content) if !content.trim_end_matches(['\n', '\r']).is_empty() =>
+      {
+        reindented += new.0;
+        reindented += content;
+      }
+      _ => reindented += line,
+    }
+  }
+  Cow::Owned(reindented)
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  #[test]
+  fn test_find_user_regions()
+  {
+    let find = |code| find_user_regions(code, &Marker_Style::DEFAULT);
+    let region = |name, code, line, nested| User_Region{name, code, line, indentation: Indentation(""), nested};
+
+    assert_eq!(find("a\nb\n"), Ok(vec![]));
+    assert_eq!(find("a\n// << keep x >>\nb\n// << /keep >>\nc\n"), Ok(vec![region("x", 18..20, 2, false)]));
+    assert_eq!(find("a\n\t  // << keep x >>\n\t  // << /keep >>\n"), Ok(vec![User_Region{indentation: Indentation("\t  "), ..region("x", 21..21, 2, false)}]));
+    assert_eq!(find("<< keep x >>\n<< /keep >>\n<< codegen inner >>\n<< keep x >>\n<< /keep >>\n<< /codegen >>\n"), Ok(vec![region("x", 13..13, 1, false), region("x", 58..58, 4, true)]));
+
+    assert_eq!(find("<< keep x >>\nb\n"), Err(User_Region_Error::UNTERMINATED{name: "x".to_owned(), line: 1}));
+    assert_eq!(find("b\n<< /keep >>\n"), Err(User_Region_Error::ORPHAN_END_MARKER{line: 2}));
+    assert_eq!(find("<< keep x >>\n<< keep y >>\n"), Err(User_Region_Error::NESTED{name: "y".to_owned(), outer_name: "x".to_owned(), line: 2}));
+    assert_eq!(find("<< keep x >>\n<< /keep >>\n<< keep x >>\n<< /keep >>\n"), Err(User_Region_Error::DUPLICATE{name: "x".to_owned(), line: 3}));
+  }
+
+  #[test]
+  fn test_strip_and_restore()
+  {
+    let strip = |code| strip_user_regions(code, &Marker_Style::DEFAULT).unwrap();
+    let restore = |new_code, old_code| restore_user_regions(new_code, old_code, &Marker_Style::DEFAULT);
+
+    assert_eq!(strip("a\n<< keep x >>\nmine\n<< /keep >>\nb"), "a\n<< keep x >>\n<< /keep >>\nb");
+
+    let old = "a\n<< keep x >>\nmine\n<< /keep >>\nb\n";
+    assert_eq!(restore("c\n<< keep x >>\n<< /keep >>\n", old), Ok("c\n<< keep x >>\nmine\n<< /keep >>\n".to_owned()));
+    assert_eq!(restore("<< keep y >>\ndefault\n<< /keep >>\n<< keep x >>\n<< /keep >>\n", old), Ok("<< keep y >>\ndefault\n<< /keep >>\n<< keep x >>\nmine\n<< /keep >>\n".to_owned()));
+    assert_eq!(restore("c\n", old), Err(User_Region_Error::MISSING{name: "x".to_owned(), line: 2}));
+
+    // The content moves along with the marker
+    let old = "{\n  << keep x >>\n  mine\n\n    deeper\nleft\n  << /keep >>\n}\n";
+    assert_eq!(restore("{\n  {\n    << keep x >>\n    << /keep >>\n  }\n}\n", old), Ok("{\n  {\n    << keep x >>\n    mine\n\n      deeper\nleft\n    << /keep >>\n  }\n}\n".to_owned()));
+    assert_eq!(restore("<< keep x >>\n<< /keep >>\n", old), Ok("<< keep x >>\nmine\n\n  deeper\nleft\n<< /keep >>\n".to_owned()));
+  }
+}
+
+use super::Marker_Style;
+use crate::indentation::Indentation;
+use super::parser::{parse_line, parse_keep, Line, Keep_Line};
+use std::borrow::Cow;
+use std::ops::Range;
+use thiserror::Error;
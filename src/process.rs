@@ -1,42 +1,303 @@
 use super::*;
 
-pub fn process_file<P, F>(path: P, cfg: Config, f: &F) -> Result
-where F: Fn(&str) -> Fmt_Result,
+/// Generates the sections of the file at `path` with `f` and writes it, if
+/// any section changed.
+///
+/// See [`gen::generate`] for the generators accepted. [`Section_Context::path`]
+/// is set to `path`.
+pub fn process_file<P, G, I>(path: P, cfg: Config, f: G) -> Result<(), Process_Error<G::Error>>
+where G: Generator<I>,
       P: AsRef<Path>,
 {
   let path = path.as_ref();
 
-  let input = std::fs::read_to_string(path)?;
+  let io_error = |error| Process_Error::IO{path: path.to_owned(), error};
 
-  if let Some(generated) = gen::generate(&input, cfg, f)?
+  let input = std::fs::read_to_string(path).map_err(io_error)?;
+
+  if let Some(generated) = gen::generate_file(&input, cfg, Some(path), f)?
   {
-    std::fs::write(path, generated)?;
+    std::fs::write(path, generated).map_err(io_error)?;
   }
 
   Ok(())
 }
 
-pub fn process_files<P, F>(paths: &[P], cfg: Config, f: F) -> Result
-where F: Fn(&str) -> Fmt_Result,
+pub fn process_files<P, G, I>(paths: &[P], cfg: Config, mut f: G) -> Result<(), Process_Error<G::Error>>
+where G: Generator<I>,
       P: AsRef<Path>,
 {
   for path in paths
   {
-    process_file(path, cfg, &f)?;
+    process_file(path, cfg, |ctx: &Section_Context| f.generate(ctx))?;
   }
 
   Ok(())
 }
 
+/// Like [`process_file`], but also returns what happened to each section,
+/// e.g. which hand edits were overwritten.
+///
+/// See [`gen::generate_with_report`].
+pub fn process_file_with_report<P, G, I>(path: P, cfg: Config, f: G) -> Result<Generate_Report, Process_Error<G::Error>>
+where G: Generator<I>,
+      P: AsRef<Path>,
+{
+  let path = path.as_ref();
+
+  let io_error = |error| Process_Error::IO{path: path.to_owned(), error};
+
+  let input = std::fs::read_to_string(path).map_err(io_error)?;
+
+  let (generated, report) = gen::generate_file_with_report(&input, cfg, Some(path), f)?;
+  if let Some(generated) = generated
+  {
+    std::fs::write(path, generated).map_err(io_error)?;
+  }
+
+  Ok(report)
+}
+
+/// Like [`process_files`], but also returns the reports of the files with
+/// changed sections.
+///
+/// With [`gen::Hand_Edit_Policy::OVERWRITE`], see
+/// [`Generate_Report::overwritten`] for the overwritten hand edits.
+pub fn process_files_with_report<P, G, I>(paths: &[P], cfg: Config, mut f: G) -> Result<Vec<File_Report>, Process_Error<G::Error>>
+where G: Generator<I>,
+      P: AsRef<Path>,
+{
+  let mut reports = Vec::new();
+  for path in paths
+  {
+    let report = process_file_with_report(path, cfg, |ctx: &Section_Context| f.generate(ctx))?;
+    if report.changed().next().is_some()
+    {
+      reports.push(File_Report{path: path.as_ref().to_owned(), report});
+    }
+  }
+
+  Ok(reports)
+}
+
+/// Like [`process_file`], but only reports the sections which would change.
+///
+/// Never writes to `path`. See [`gen::check`].
+pub fn check_file<P, G, I>(path: P, cfg: Config, f: G) -> Result<Vec<Stale_Section>, Process_Error<G::Error>>
+where G: Generator<I>,
+      P: AsRef<Path>,
+{
+  let path = path.as_ref();
+
+  let input = std::fs::read_to_string(path).map_err(|error| Process_Error::IO{path: path.to_owned(), error})?;
+
+  Ok(gen::check_file(&input, cfg, Some(path), f)?)
+}
+
+/// Like [`process_files`], but only reports the files with sections which
+/// would change.
+///
+/// Never writes to any of the `paths`.
+pub fn check_files<P, G, I>(paths: &[P], cfg: Config, mut f: G) -> Result<Vec<Stale_File>, Process_Error<G::Error>>
+where G: Generator<I>,
+      P: AsRef<Path>,
+{
+  let mut stale_files = Vec::new();
+  for path in paths
+  {
+    let sections = check_file(path, cfg, |ctx: &Section_Context| f.generate(ctx))?;
+    if !sections.is_empty()
+    {
+      stale_files.push(Stale_File{path: path.as_ref().to_owned(), sections});
+    }
+  }
+
+  Ok(stale_files)
+}
+
+/// Whether [`diff_file`] and [`diff_files`] write the regenerated files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diff_Mode
+{
+  /// Only return the diff
+  DRY_RUN,
+  /// Write the regenerated files and return the diff of the changes
+  WRITE,
+}
+
+/// Returns a unified diff of the changes [`process_file`] makes to `path`.
+///
+/// The diff has one hunk per changed section, annotated with its identifier,
+/// and can be applied with `patch -p0`. Returns `None` if nothing changes.
+pub fn diff_file<P, G, I>(path: P, cfg: Config, mode: Diff_Mode, f: G) -> Result<Option<String>, Process_Error<G::Error>>
+where G: Generator<I>,
+      P: AsRef<Path>,
+{
+  let path = path.as_ref();
+
+  let io_error = |error| Process_Error::IO{path: path.to_owned(), error};
+
+  let input = std::fs::read_to_string(path).map_err(io_error)?;
+
+  let label = path.display().to_string();
+  let (generated, diff) = match gen::diff_file(&input, cfg, Some(path), &label, f)?
+  {
+    Some(x) => x,
+    None => return Ok(None),
+  };
+
+  if mode == Diff_Mode::WRITE
+  {
+    std::fs::write(path, generated).map_err(io_error)?;
+  }
+
+  Ok(Some(diff))
+}
+
+/// Like [`diff_file`], but for multiple files. The diffs are concatenated.
+pub fn diff_files<P, G, I>(paths: &[P], cfg: Config, mode: Diff_Mode, mut f: G) -> Result<Option<String>, Process_Error<G::Error>>
+where G: Generator<I>,
+      P: AsRef<Path>,
+{
+  let mut diffs : Option<String> = None;
+  for path in paths
+  {
+    if let Some(diff) = diff_file(path, cfg, mode, |ctx: &Section_Context| f.generate(ctx))?
+    {
+      diffs.get_or_insert_with(String::new).push_str(&diff);
+    }
+  }
+
+  Ok(diffs)
+}
+
+/// A file with sections which would change by processing it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stale_File
+{
+  pub path: PathBuf,
+  pub sections: Vec<Stale_Section>,
+}
+
+/// A file with sections changed by [`process_files_with_report`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct File_Report
+{
+  pub path: PathBuf,
+  pub report: Generate_Report,
+}
+
 pub type Result<T=(), E=Error> = std::result::Result<T, E>;
 
 #[derive(Debug, thiserror::Error)]
-pub enum Process_Error
+pub enum Process_Error<E=std::fmt::Error>
 {
-  #[error("{0}")]
-  IO(#[from] std::io::Error),
+  #[error("{}: {error}", path.display())]
+  IO{path: PathBuf, error: std::io::Error},
   #[error("Generation error: {0}")]
-  GEN(#[from] gen::Gen_Error),
+  GEN(#[from] gen::Gen_Error<E>),
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+  use gen::Stale_Reason;
+
+  #[test]
+  fn test_check_files()
+  {
+    let dir = std::env::temp_dir().join(format!("codebiber-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let outdated = dir.join("outdated.txt");
+    let up_to_date = dir.join("up_to_date.txt");
+    std::fs::write(&outdated, "<< codegen foo >>\n<< /codegen >>\n").unwrap();
+    std::fs::write(&up_to_date, "<< codegen foo >>\n42\n<< /codegen >>\n").unwrap();
+
+    let stale = check_files(&[&outdated, &up_to_date], Config::DEFAULT, |_: &str| Some("42".to_owned())).unwrap();
+
+    assert_eq!(stale, vec![Stale_File{path: outdated.clone(), sections: vec![Stale_Section{identifier: "foo".to_owned(), lines: 1..=2, reason: Stale_Reason::CONTENT_CHANGED}]}]);
+    assert_eq!(std::fs::read_to_string(&outdated).unwrap(), "<< codegen foo >>\n<< /codegen >>\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_process_files()
+  {
+    let dir = std::env::temp_dir().join(format!("codebiber-process-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    std::fs::write(&a, "<< codegen foo >>\n<< /codegen >>\n").unwrap();
+    std::fs::write(&b, "<< codegen bar >>\nold\n<< /codegen >>\n").unwrap();
+
+    let mut calls = vec![];
+    process_files(&[&a, &b], Config::DEFAULT, |ctx: &Section_Context| {
+      calls.push(ctx.path.unwrap().to_owned());
+      match ctx.identifier
+      {
+        "foo" => gen::Generator_Output::CODE("42".to_owned()),
+        _ => gen::Generator_Output::KEEP,
+      }
+    }).unwrap();
+    assert_eq!(calls, vec![a.clone(), b.clone()]);
+    assert_eq!(std::fs::read_to_string(&a).unwrap(), "<< codegen foo >>\n42\n<< /codegen >>\n");
+    assert_eq!(std::fs::read_to_string(&b).unwrap(), "<< codegen bar >>\nold\n<< /codegen >>\n");
+
+    let error = process_files(&[&a, &b], Config::DEFAULT, |name: &str| match name
+    {
+      "foo" => Ok(Some("42".to_owned())),
+      _ => Err("unknown section"),
+    }).unwrap_err();
+    assert!(matches!(error, Process_Error::GEN(gen::Gen_Error::GENERATOR{error: "unknown section", ..})));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_process_files_with_report()
+  {
+    let dir = std::env::temp_dir().join(format!("codebiber-report-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let edited = dir.join("edited.txt");
+    let up_to_date = dir.join("up_to_date.txt");
+    std::fs::write(&edited, "<< codegen foo >>\n43\n<< /codegen a160 >>\n").unwrap();
+    std::fs::write(&up_to_date, "<< codegen foo >>\n42\n<< /codegen a160 >>\n").unwrap();
+
+    let cfg = Config{on_hand_edit: gen::Hand_Edit_Policy::OVERWRITE, checksum_bytes_to_store: 2, ..Config::DEFAULT};
+    let reports = process_files_with_report(&[&edited, &up_to_date], cfg, |_: &str| Some("42".to_owned())).unwrap();
+
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].path, edited);
+    let overwritten : Vec<_> = reports[0].report.overwritten().map(|section| (section.identifier.as_str(), section.old_lines.clone())).collect();
+    assert_eq!(overwritten, vec![("foo", 1..=3)]);
+    assert_eq!(std::fs::read_to_string(&edited).unwrap(), "<< codegen foo >>\n42\n<< /codegen a160 >>\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_diff_files()
+  {
+    let dir = std::env::temp_dir().join(format!("codebiber-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("foo.txt");
+    std::fs::write(&path, "<< codegen foo >>\n<< /codegen >>\n").unwrap();
+
+    let gen = |_: &str| Some("42".to_owned());
+    let expected = format!("--- {0}\n+++ {0}\n@@ -1,2 +1,3 @@ foo\n << codegen foo >>\n+42\n << /codegen >>\n", path.display());
+
+    assert_eq!(diff_files(&[&path], Config::DEFAULT, Diff_Mode::DRY_RUN, gen).unwrap(), Some(expected.clone()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "<< codegen foo >>\n<< /codegen >>\n");
+
+    assert_eq!(diff_files(&[&path], Config::DEFAULT, Diff_Mode::WRITE, gen).unwrap(), Some(expected));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "<< codegen foo >>\n42\n<< /codegen >>\n");
+    assert_eq!(diff_files(&[&path], Config::DEFAULT, Diff_Mode::DRY_RUN, gen).unwrap(), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
 }
 
-use std::path::Path;
\ No newline at end of file
+use gen::{Generator, Section_Context, Generate_Report, Stale_Section};
+use std::path::{Path, PathBuf};
\ No newline at end of file
@@ -0,0 +1,179 @@
+/// Lines of context around the changes of a section, if the section is long
+/// enough
+const CONTEXT : usize = 3;
+
+/// A section to be diffed, with the 1-based line numbers of its begin and end
+/// marker in the old and new text
+pub struct Changed_Section<'a>
+{
+  pub identifier: &'a str,
+  /// Annotates the hunk, as a hand edit is overwritten
+  pub hand_edited: bool,
+  pub old_lines: RangeInclusive<usize>,
+  pub new_lines: RangeInclusive<usize>,
+}
+
+/// Writes a unified diff between `old` and `new` with one hunk per section.
+///
+/// The hunks are limited to the sections and annotated with their identifier.
+/// Returns `None` if no section differs.
+pub fn unified_diff<'a>(old: &str, new: &str, old_label: &str, new_label: &str, sections: impl IntoIterator<Item=Changed_Section<'a>>) -> Option<String>
+{
+  let old_lines : Vec<&str> = old.split_inclusive('\n').collect();
+  let new_lines : Vec<&str> = new.split_inclusive('\n').collect();
+
+  let mut diff = String::new();
+  for section in sections
+  {
+    let old = &old_lines[section.old_lines.start()-1 .. *section.old_lines.end()];
+    let new = &new_lines[section.new_lines.start()-1 .. *section.new_lines.end()];
+    let label = match section.hand_edited
+    {
+      true => format!("{} (hand-edited)", section.identifier),
+      false => section.identifier.to_owned(),
+    };
+    write_hunk(&mut diff, &label, old, *section.old_lines.start(), new, *section.new_lines.start());
+  }
+
+  match diff.is_empty()
+  {
+    true => None,
+    false => Some(format!("--- {old_label}\n+++ {new_label}\n{diff}")),
+  }
+}
+
+fn write_hunk(out: &mut String, label: &str, old: &[&str], old_start: usize, new: &[&str], new_start: usize)
+{
+  let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+  let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+  if prefix == old.len() && prefix == new.len()
+  {
+    return;
+  }
+
+  // `patch` needs fuzz to apply hunks with less context on one side
+  let context = prefix.min(suffix).min(CONTEXT);
+  let old = &old[prefix-context .. old.len()-suffix+context];
+  let new = &new[prefix-context .. new.len()-suffix+context];
+  let old_start = old_start + prefix - context;
+  let new_start = new_start + prefix - context;
+
+  let _ = writeln!(out, "@@ -{} +{} @@ {label}", range(old_start, old.len()), range(new_start, new.len()));
+  for (op, line) in diff_lines(old, new)
+  {
+    out.push(op);
+    out.push_str(line);
+    if !line.ends_with('\n')
+    {
+      out.push_str("\n\\ No newline at end of file\n");
+    }
+  }
+}
+
+fn range(start: usize, len: usize) -> String
+{
+  match len
+  {
+    0 => format!("{},0", start-1),
+    1 => format!("{start}"),
+    len => format!("{start},{len}"),
+  }
+}
+
+/// Line diff based on the longest common subsequence
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)>
+{
+  let (n, m) = (old.len(), new.len());
+  let mut lcs = vec![0_usize; (n+1)*(m+1)];
+  let at = |i: usize, j: usize| i*(m+1)+j;
+  for i in (0..n).rev()
+  {
+    for j in (0..m).rev()
+    {
+      lcs[at(i, j)] = match old[i] == new[j]
+      {
+        true => lcs[at(i+1, j+1)] + 1,
+        false => lcs[at(i+1, j)].max(lcs[at(i, j+1)]),
+      };
+    }
+  }
+
+  let mut ops = Vec::with_capacity(n+m);
+  let (mut i, mut j) = (0, 0);
+  while i < n || j < m
+  {
+    if i < n && j < m && old[i] == new[j]
+    {
+      ops.push((' ', old[i]));
+      i += 1;
+      j += 1;
+    }
+    else if j == m || (i < n && lcs[at(i+1, j)] >= lcs[at(i, j+1)])
+    {
+      ops.push(('-', old[i]));
+      i += 1;
+    }
+    else
+    {
+      ops.push(('+', new[j]));
+      j += 1;
+    }
+  }
+  ops
+}
+
+#[cfg(test)]
+mod test
+{
+  use super::*;
+
+  fn section(identifier: &str, old_lines: RangeInclusive<usize>, new_lines: RangeInclusive<usize>) -> Changed_Section<'_>
+  {
+    Changed_Section{identifier, hand_edited: false, old_lines, new_lines}
+  }
+
+  #[test]
+  fn test_unchanged()
+  {
+    assert_eq!(unified_diff("a\nb\n", "a\nb\n", "x", "y", [section("foo", 1..=2, 1..=2)]), None);
+    assert_eq!(unified_diff("a\nb\n", "a\nb\n", "x", "y", []), None);
+  }
+
+  #[test]
+  fn test_hunks()
+  {
+    let old = "x\n<< codegen foo >>\na\nb\n<< /codegen >>\ny\n<< codegen bar >>\n<< /codegen >>\n";
+    let new = "x\n<< codegen foo >>\na\nc\nd\n<< /codegen >>\ny\n<< codegen bar >>\n42\n<< /codegen >>";
+    let diff = unified_diff(old, new, "foo.rs", "foo.rs", [section("foo", 2..=5, 2..=6), section("bar", 7..=8, 8..=10)]).unwrap();
+    assert_eq!(diff, r"--- foo.rs
++++ foo.rs
+@@ -3,3 +3,4 @@ foo
+ a
+-b
++c
++d
+ << /codegen >>
+@@ -8 +9,2 @@ bar
+-<< /codegen >>
++42
++<< /codegen >>
+\ No newline at end of file
+");
+  }
+
+  #[test]
+  fn test_context()
+  {
+    let old = "<< codegen foo >>\n1\n2\n3\n4\n5\n6\n7\n8\n<< /codegen >>\n";
+    let new = "<< codegen foo >>\n1\n2\n3\n4\nfive\n6\n7\n8\n<< /codegen >>\n";
+    let diff = unified_diff(old, new, "a", "b", [section("foo", 1..=10, 1..=10)]).unwrap();
+    assert_eq!(diff, "--- a\n+++ b\n@@ -3,7 +3,7 @@ foo\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n");
+
+    let new = "<< codegen foo >>\n1\n2\n3\n4\n5\n6\n7\n8\n<< /codegen 42 >>\n";
+    let diff = unified_diff(old, new, "a", "b", [section("foo", 1..=10, 1..=10)]).unwrap();
+    assert_eq!(diff, "--- a\n+++ b\n@@ -10 +10 @@ foo\n-<< /codegen >>\n+<< /codegen 42 >>\n");
+  }
+}
+
+use std::fmt::Write;
+use std::ops::RangeInclusive;
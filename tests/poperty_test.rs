@@ -1,9 +1,9 @@
 #![allow(non_camel_case_types)]
-#![feature(string_remove_matches)]
+#![allow(clippy::upper_case_acronyms, clippy::large_enum_variant)]
 
 extern crate codebiber;
 use codebiber::{
-  Indentation, Config, generate,
+  Indentation, Config, Marker_Style, generate,
 };
 
 extern crate proptest;
@@ -21,7 +21,7 @@ use unwrap_display::UnwrapDisplay;
 enum Section
 {
   HANDWRITTEN(String),
-  GENERATED{code: String, name: String, surround: Surround, generated_with_config: Config, action: Action},
+  GENERATED{code: String, name: String, surround: Surround, generated_with_config: Config<'static>, action: Action},
 }
 
 #[derive(Clone, Debug)]
@@ -35,7 +35,7 @@ enum Action
 use Section::*;
 use Action::*;
 
-fn format_input(sections: &[Section]) -> String
+fn format_input(sections: &[Section], style: Marker_Style) -> String
 {
   let mut out = String::new();
   for s in sections.iter()
@@ -44,7 +44,7 @@ fn format_input(sections: &[Section]) -> String
     {
       HANDWRITTEN(c) => out += c.as_str(),
       GENERATED{code, name, generated_with_config, surround, action: _} =>
-        format_generated_code(&mut out, code.as_str(), name.as_str(), surround, *generated_with_config).unwrap(),
+        format_generated_code(&mut out, code.as_str(), name.as_str(), surround, Config{marker_style: style, ..*generated_with_config}).unwrap(),
     }
   }
   out
@@ -60,7 +60,7 @@ fn format_expected_output(sections: &[Section], cfg: Config) -> Option<String>
     {
       HANDWRITTEN(c) => out += c.as_str(),
       GENERATED{code: old_code, name, generated_with_config, surround, action} => {
-        has_some_change = has_some_change || generated_with_config!=&cfg;
+        has_some_change = has_some_change || generated_with_config.checksum_bytes_to_store!=cfg.checksum_bytes_to_store;
         let code = match action {
           SKIP | KEEP => old_code,
           REPLACE_WITH(new_code) => {has_some_change = has_some_change || new_code!=old_code; new_code}
@@ -82,17 +82,17 @@ fn format_generated_code(out: &mut String, code: &str, name: &str, surround: &Su
   let mut code = code.to_owned();
   ensure_newline(&mut code);
   let hash = blake3::hash(code.as_bytes()).to_hex();
-  let code = surround.indent.indent_str(code.as_str());
+  let code = Indentation(&surround.indent).indent_str(code.as_str());
 
   use std::fmt::Write;
-  surround.begin(out, Some(name))?;
+  surround.begin(out, config.marker_style, Some(name))?;
   write!(out, "{code}")?;
   let suffix = match config.checksum_bytes_to_store
   {
     0 => None,
     n => Some(&hash[..2*n as usize]),
   };
-  surround.end::<&str>(out, suffix)
+  surround.end::<&str>(out, config.marker_style, suffix)
 }
 
 #[derive(Clone, Debug)]
@@ -100,7 +100,7 @@ struct Surround
 {
   begin: Surround_Marker,
   end: Surround_Marker,
-  indent: Indentation,
+  indent: String,
 }
 
 #[derive(Clone, Debug)]
@@ -112,30 +112,30 @@ struct Surround_Marker
 
 impl Surround
 {
-  fn begin<Suffix: std::fmt::Display>(&self, out: &mut String, suffix: Option<Suffix>) -> std::fmt::Result
+  fn begin<Suffix: std::fmt::Display>(&self, out: &mut String, style: Marker_Style, suffix: Option<Suffix>) -> std::fmt::Result
   {
-    self.begin.write(out, false, self.indent, suffix)
+    self.begin.write(out, style.begin_keyword, style, Indentation(&self.indent), suffix)
   }
 
-  fn end<Suffix: std::fmt::Display>(&self, out: &mut String, suffix: Option<Suffix>) -> std::fmt::Result
+  fn end<Suffix: std::fmt::Display>(&self, out: &mut String, style: Marker_Style, suffix: Option<Suffix>) -> std::fmt::Result
   {
-    self.end.write(out, true, self.indent, suffix)
+    self.end.write(out, style.end_keyword, style, Indentation(&self.indent), suffix)
   }
 }
 
 impl Surround_Marker
 {
-  fn write<Suffix: std::fmt::Display>(&self, out: &mut String, closing: bool, indent: Indentation, suffix: Option<Suffix>) -> std::fmt::Result
+  fn write<Suffix: std::fmt::Display>(&self, out: &mut String, keyword: &str, style: Marker_Style, indent: Indentation<'_>, suffix: Option<Suffix>) -> std::fmt::Result
   {
     use std::fmt::Write;
     ensure_newline(out);
     
-    write!(out, "{}{}<< {}codegen", indent, self.before, if closing {"/"} else {""})?;
+    write!(out, "{}{}{} {}", indent, self.before, style.open, keyword)?;
     if let Some(suffix) = suffix
     {
       write!(out, " {}", suffix)?;
     }
-    writeln!(out, " >>{}", self.after)?;
+    writeln!(out, " {}{}", style.close, self.after)?;
 
     Ok(())
   }
@@ -144,10 +144,11 @@ impl Surround_Marker
 proptest!
 {
   #[test]
-  fn roundtrip(sections in many_sections(), cfg in config())
+  fn roundtrip(sections in many_sections(), cfg in config(), style in marker_style(), crlf in prop::bool::ANY)
   {
-    let input = format_input(&sections[..]);
-    let expected = format_expected_output(&sections[..], cfg);
+    let cfg = Config{marker_style: style, ..cfg};
+    let input = with_line_ending(format_input(&sections[..], style), crlf);
+    let expected = format_expected_output(&sections[..], cfg).map(|x| with_line_ending(x, crlf));
 
     let mut codes : Vec<Option<String>> = sections.iter().filter_map(|s| match s {
         HANDWRITTEN(..) => None,
@@ -156,7 +157,7 @@ proptest!
         | GENERATED { action: REPLACE_WITH(code), .. } => Some(Some(code.clone())),
     }).collect();
     codes.reverse();
-    let actual = generate(input.as_str(), cfg, move |_| Ok(codes.pop().unwrap())).expect_display_code(input.as_str());
+    let actual = generate(input.as_str(), cfg, move |_: &str| codes.pop().unwrap()).expect_display_code(input.as_str());
 
     assert_eq!(actual, expected, "   input: {:?}", input.as_str());
   }
@@ -167,7 +168,7 @@ fn many_sections() -> impl Strategy<Value = Vec<Section>>
   let action = prop_oneof![
     Just(SKIP),
     Just(KEEP),
-    code().prop_map(|code| REPLACE_WITH(code)),
+    code().prop_map(REPLACE_WITH),
   ];
 
   let surround = surround();
@@ -181,27 +182,34 @@ fn many_sections() -> impl Strategy<Value = Vec<Section>>
   prop::collection::vec(section, 0..16)
 }
 
-fn config() -> impl Strategy<Value = Config>
+fn config() -> impl Strategy<Value = Config<'static>>
 {
   prop_oneof![
     (0..(blake3::KEY_LEN as u8)).prop_map(|checksum_bytes_to_store|
       Config{
         checksum_bytes_to_store,
+        ..Config::DEFAULT
     }),
   ]
 }
 
+fn marker_style() -> impl Strategy<Value = Marker_Style<'static>>
+{
+  prop_oneof![
+    Just(Marker_Style::DEFAULT),
+    Just(Marker_Style::BRACKETS),
+  ]
+}
+
 fn surround() -> impl Strategy<Value = Surround>
 {
-  let indent = (..u8::MAX).prop_map(|i| Indentation(i.into()));
-  let surround = (surround_marker(), surround_marker(), indent).prop_map(|(begin, end, indent)| Surround{begin, end, indent});
-  surround
+  let indent = "[ \t]{0,32}";
+  (surround_marker(), surround_marker(), indent).prop_map(|(begin, end, indent)| Surround{begin, end, indent})
 }
 
 fn surround_marker() -> impl Strategy<Value = Surround_Marker>
 {
-  let surround = (before_marker(), after_marker()).prop_map(|(before, after)| Surround_Marker{before, after});
-  surround
+  (before_marker(), after_marker()).prop_map(|(before, after)| Surround_Marker{before, after})
 }
 
 fn code() -> impl Strategy<Value = String>
@@ -213,7 +221,7 @@ fn code() -> impl Strategy<Value = String>
 
 fn before_marker() -> impl Strategy<Value = String>
 {
-  "([^ \n]+[^\n]*)?"
+  "([^ \t\r\n]+[^\r\n]*)?"
   .prop_filter("regular code is not allowed to contain `<< codegen`",
     |code| no_marker(code) && !code.ends_with('<') && !code.contains("<<"))
   .prop_filter("surround_marker() /* before */",
@@ -222,12 +230,12 @@ fn before_marker() -> impl Strategy<Value = String>
 
 fn after_marker() -> impl Strategy<Value = String>
 {
-  "([^\n]*[^ \n][^\n]*)?".prop_filter(
+  "([^\r\n]*[^ \r\n][^\r\n]*)?".prop_filter(
     "regular code is not allowed to contain `<< codegen`",
     no_marker)
 }
 
-fn no_marker<S: AsRef<str>>(code: &S) -> bool {!regex_is_match!("<< *\\/?codegen", code.as_ref())}
+fn no_marker<S: AsRef<str>>(code: &S) -> bool {!regex_is_match!("(<<|\\[\\[\\[) *\\/?codegen", code.as_ref())}
 
 fn ident() -> impl Strategy<Value = String>
 {
@@ -251,10 +259,19 @@ fn set_tailing_linebreak(mut code: String, expect_tailing_linebreak: bool) -> St
 
 fn remove_carriage_return(mut code: String) -> String
 {
-  code.remove_matches('\r');
+  code.retain(|c| c != '\r');
   code
 }
 
+fn with_line_ending(code: String, crlf: bool) -> String
+{
+  match crlf
+  {
+    true => code.replace('\n', "\r\n"),
+    false => code,
+  }
+}
+
 fn ensure_newline(code: &mut String)
 {
   if !code.is_empty() && !code.ends_with('\n') {code.push('\n');}